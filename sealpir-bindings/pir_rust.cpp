@@ -1,126 +1,1019 @@
 #include "pir_rust.hpp"
 
-void *new_parameters(uint32_t ele_num, uint32_t ele_size, uint32_t N, uint32_t logt, uint32_t d) {
-    Parameters *param = new Parameters;
-    gen_params(ele_num, ele_size, N, logt, d, param->params, param->pir_params);
-    return (void *)param;
+#include <algorithm>
+#include <limits>
+#include <random>
+#include <seal/util/polyarithsmallmod.h>
+#include <seal/util/smallntt.h>
+#include <sstream>
+#include <stdexcept>
+
+namespace {
+
+char *copy_message(const char *msg) {
+    size_t len = strlen(msg);
+    char *out = (char *)malloc(len + 1);
+    if (out != nullptr) {
+        memcpy(out, msg, len + 1);
+    }
+    return out;
+}
+
+uint8_t *copy_bytes(const string &ser, uint32_t *size) {
+    uint8_t *out = (uint8_t *)malloc(ser.size());
+    if (out == nullptr) {
+        throw std::bad_alloc();
+    }
+    memcpy(out, ser.data(), ser.size());
+    *size = ser.size();
+    return out;
+}
+
+// runs body and converts any exception it throws into a status code and error message so
+// that no exception unwinds into the (Rust) caller
+template <typename F> int32_t guard(char **error, F &&body) {
+    *error = nullptr;
+    try {
+        body();
+        return PIR_OK;
+    } catch (const std::invalid_argument &e) {
+        *error = copy_message(e.what());
+        return PIR_INVALID_ARGUMENT;
+    } catch (const std::logic_error &e) {
+        *error = copy_message(e.what());
+        return PIR_LOGIC_ERROR;
+    } catch (const std::exception &e) {
+        *error = copy_message(e.what());
+        return PIR_ERROR;
+    } catch (...) {
+        *error = copy_message("unknown exception");
+        return PIR_ERROR;
+    }
+}
+
+// Stream buffers over memory owned by the caller. Serializing a secret key through a
+// std::stringstream leaves copies of it in buffers the caller cannot wipe; these read and
+// write the caller's buffer directly.
+class SpanOutBuf : public std::streambuf {
+  public:
+    // data may be null to only count the bytes written
+    SpanOutBuf(uint8_t *data, size_t size) : data_(data), size_(size), count_(0) {}
+
+    size_t count() const { return count_; }
+
+  protected:
+    std::streamsize xsputn(const char *s, std::streamsize n) override {
+        if (data_ != nullptr && count_ < size_) {
+            memcpy(data_ + count_, s, std::min((size_t)n, size_ - count_));
+        }
+        count_ += n;
+        return n;
+    }
+
+    int_type overflow(int_type c) override {
+        if (!traits_type::eq_int_type(c, traits_type::eof())) {
+            char ch = traits_type::to_char_type(c);
+            xsputn(&ch, 1);
+        }
+        return traits_type::not_eof(c);
+    }
+
+  private:
+    uint8_t *data_;
+    size_t size_;
+    size_t count_;
+};
+
+class SpanInBuf : public std::streambuf {
+  public:
+    SpanInBuf(const uint8_t *data, size_t size) {
+        char *begin = (char *)data; // only read
+        setg(begin, begin, begin + size);
+    }
+};
+
+// SealPIR keeps the database private. Explicit template instantiations may name private
+// members, which gives the bindings a pointer to them without patching SealPIR.
+template <typename Tag, typename Tag::type Member> struct Access {
+    friend typename Tag::type member(Tag) { return Member; }
+};
+
+struct ServerDb {
+    typedef std::unique_ptr<Database> PIRServer::*type;
+    friend type member(ServerDb);
+};
+
+struct ServerDbPreprocessed {
+    typedef bool PIRServer::*type;
+    friend type member(ServerDbPreprocessed);
+};
+
+struct ServerGaloisKeys {
+    typedef std::map<int, seal::GaloisKeys> PIRServer::*type;
+    friend type member(ServerGaloisKeys);
+};
+
+struct ServerEvaluator {
+    typedef std::unique_ptr<seal::Evaluator> PIRServer::*type;
+    friend type member(ServerEvaluator);
+};
+
+struct ServerDecompose {
+    typedef void (PIRServer::*type)(const seal::Ciphertext &, seal::Plaintext *, int);
+    friend type member(ServerDecompose);
+};
+
+struct ServerMultiplyPowerOfX {
+    typedef void (PIRServer::*type)(const seal::Ciphertext &, seal::Ciphertext &, uint32_t);
+    friend type member(ServerMultiplyPowerOfX);
+};
+
+struct ClientContext {
+    typedef std::shared_ptr<seal::SEALContext> PIRClient::*type;
+    friend type member(ClientContext);
+};
+
+struct ClientKeygen {
+    typedef std::unique_ptr<seal::KeyGenerator> PIRClient::*type;
+    friend type member(ClientKeygen);
+};
+
+struct ClientEncryptor {
+    typedef std::unique_ptr<seal::Encryptor> PIRClient::*type;
+    friend type member(ClientEncryptor);
+};
+
+struct ClientDecryptor {
+    typedef std::unique_ptr<seal::Decryptor> PIRClient::*type;
+    friend type member(ClientDecryptor);
+};
+
+struct ClientCompose {
+    typedef seal::Ciphertext (PIRClient::*type)(std::vector<seal::Plaintext>);
+    friend type member(ClientCompose);
+};
+
+struct GaloisKeysBitCount {
+    typedef int seal::GaloisKeys::*type;
+    friend type member(GaloisKeysBitCount);
+};
+
+template struct Access<ClientContext, &PIRClient::newcontext_>;
+template struct Access<ClientKeygen, &PIRClient::keygen_>;
+template struct Access<ClientEncryptor, &PIRClient::encryptor_>;
+template struct Access<ClientDecryptor, &PIRClient::decryptor_>;
+template struct Access<ClientCompose, &PIRClient::compose_to_ciphertext>;
+template struct Access<ServerDb, &PIRServer::db_>;
+template struct Access<ServerDbPreprocessed, &PIRServer::is_db_preprocessed_>;
+template struct Access<ServerGaloisKeys, &PIRServer::galoisKeys_>;
+template struct Access<ServerEvaluator, &PIRServer::evaluator_>;
+template struct Access<ServerDecompose, &PIRServer::decompose_to_plaintexts_ptr>;
+template struct Access<ServerMultiplyPowerOfX, &PIRServer::multiply_power_of_X>;
+template struct Access<GaloisKeysBitCount, &seal::GaloisKeys::decomposition_bit_count_>;
+
+// the preprocessed database of server; throws if there is none
+Database &preprocessed_db(const PIRServer *server) {
+    const std::unique_ptr<Database> &db = server->*member(ServerDb());
+    if (db == nullptr || !(server->*member(ServerDbPreprocessed()))) {
+        throw std::logic_error("database has not been preprocessed");
+    }
+    return *db;
+}
+
+// Mirrors PIRClient::decode_reply, which decrypts the ciphertexts of the last dimension,
+// composes every expansion_ratio plaintexts into a ciphertext of the previous dimension and
+// so on, but also writes the smallest invariant noise budget met on the way from each reply
+// ciphertext to the final plaintext to budgets, one entry per reply ciphertext.
+seal::Plaintext decode_layers(PIRClient *client, const PirParams &pir_params,
+                              const PirReply &reply, uint32_t *budgets) {
+    seal::Decryptor &decryptor = *(client->*member(ClientDecryptor()));
+    const uint32_t ratio = pir_params.expansion_ratio;
+
+    std::fill(budgets, budgets + reply.size(), std::numeric_limits<uint32_t>::max());
+    std::vector<seal::Ciphertext> layer = reply;
+    size_t span = 1; // reply ciphertexts that went into each ciphertext of layer
+    for (uint32_t i = 0; i < pir_params.d; i++) {
+        std::vector<seal::Ciphertext> next;
+        std::vector<seal::Plaintext> plains;
+        for (size_t j = 0; j < layer.size(); j++) {
+            uint32_t budget = std::max(decryptor.invariant_noise_budget(layer[j]), 0);
+            size_t end = std::min((j + 1) * span, reply.size());
+            for (size_t k = j * span; k < end; k++) {
+                budgets[k] = std::min(budgets[k], budget);
+            }
+
+            seal::Plaintext plain;
+            decryptor.decrypt(layer[j], plain);
+            plains.push_back(plain);
+            if (i + 1 < pir_params.d && plains.size() == ratio) {
+                next.push_back((client->*member(ClientCompose()))(plains));
+                plains.clear();
+            }
+        }
+
+        // every plaintext is composed into the next dimension, except the single last one
+        bool last = i + 1 == pir_params.d;
+        if (last ? layer.size() != 1 : !plains.empty() || next.empty()) {
+            throw std::invalid_argument("reply does not have the expected number of ciphertexts");
+        }
+        if (last) {
+            return plains[0];
+        }
+        layer = std::move(next);
+        span *= ratio;
+    }
+    throw std::invalid_argument("parameters have no dimension");
+}
+
+// the Galois key registered for client_id. PIRServer::expand_query looks it up with
+// std::map::operator[], which inserts missing keys and is not safe to call concurrently;
+// find only reads the map.
+const seal::GaloisKeys &galois_key(const PIRServer *server, uint32_t client_id) {
+    const std::map<int, seal::GaloisKeys> &keys = server->*member(ServerGaloisKeys());
+    auto it = keys.find(client_id);
+    if (it == keys.end()) {
+        throw std::logic_error("no Galois key registered for the client");
+    }
+    return it->second;
+}
+
+// Mirrors PIRServer::expand_query, with the Galois key passed in: expands encrypted into m
+// ciphertexts, the i-th of which encrypts the i-th coefficient of the query.
+std::vector<seal::Ciphertext> expand_query(PIRServer *server, const Parameters *param,
+                                           const seal::GaloisKeys &galkey,
+                                           const seal::Ciphertext &encrypted, uint32_t m) {
+    const seal::Evaluator &evaluator = *(server->*member(ServerEvaluator()));
+    auto multiply_power_of_x = member(ServerMultiplyPowerOfX());
+
+    uint64_t n = param->params.poly_modulus_degree();
+    uint32_t logn = ceil(log2(n));
+    uint32_t logm = ceil(log2(m));
+    if (m == 0 || logm > logn) {
+        throw std::logic_error("m must be between 1 and n");
+    }
+
+    std::vector<uint64_t> galois_elts;
+    for (uint32_t i = 0; i < logn; i++) {
+        galois_elts.push_back((n + (1ULL << i)) / (1ULL << i));
+    }
+
+    seal::Plaintext two("2");
+    std::vector<seal::Ciphertext> temp{encrypted};
+    seal::Ciphertext rotated;
+    seal::Ciphertext shifted;
+    seal::Ciphertext rotated_shifted;
+    for (uint32_t i = 0; i < logm; i++) {
+        std::vector<seal::Ciphertext> next(temp.size() << 1);
+        uint64_t index_raw = (n << 1) - (1ULL << i);
+        uint64_t index = (index_raw * galois_elts[i]) % (n << 1);
+        bool last = i + 1 == logm;
+
+        for (size_t a = 0; a < temp.size(); a++) {
+            // in the last step, slots past m only need the scaling by 2
+            if (last && a >= m - (1ULL << (logm - 1))) {
+                evaluator.multiply_plain(temp[a], two, next[a]);
+                continue;
+            }
+            evaluator.apply_galois(temp[a], galois_elts[i], galkey, rotated);
+            evaluator.add(temp[a], rotated, next[a]);
+            (server->*multiply_power_of_x)(temp[a], shifted, index_raw);
+            (server->*multiply_power_of_x)(rotated, rotated_shifted, index);
+            evaluator.add(shifted, rotated_shifted, next[a + temp.size()]);
+        }
+        temp = std::move(next);
+    }
+
+    temp.resize(m);
+    return temp;
+}
+
+// Mirrors PIRServer::generate_reply, except that the plaintexts of the first dimension are
+// read through plain_at(index) and that the Galois key is passed in. Only reads server
+// state, so that replies may be generated concurrently.
+template <typename PlainAt>
+PirReply generate_reply_from(PIRServer *server, const Parameters *param, const PirQuery &query,
+                             const seal::GaloisKeys &galkey, uint64_t num_plaintexts,
+                             PlainAt plain_at) {
+    const seal::EncryptionParameters &params = param->params;
+    const PirParams &pir_params = param->pir_params;
+    const seal::Evaluator &evaluator = *(server->*member(ServerEvaluator()));
+
+    uint64_t N = params.poly_modulus_degree();
+    int logt = floor(log2(params.plain_modulus().value()));
+
+    if (query.size() != pir_params.nvec.size()) {
+        throw std::invalid_argument("query has the wrong number of dimensions");
+    }
+
+    uint64_t product = 1;
+    for (uint64_t n_i : pir_params.nvec) {
+        product *= n_i;
+    }
+    if (num_plaintexts != product) {
+        throw std::invalid_argument("database does not match the parameters");
+    }
+
+    std::vector<seal::Plaintext> intermediate_plain;
+
+    for (size_t i = 0; i < pir_params.nvec.size(); i++) {
+        uint64_t n_i = pir_params.nvec[i];
+
+        std::vector<seal::Ciphertext> expanded_query;
+        for (size_t j = 0; j < query[i].size(); j++) {
+            uint64_t total = (j == query[i].size() - 1) ? n_i % N : N;
+            std::vector<seal::Ciphertext> part =
+                expand_query(server, param, galkey, query[i][j], total);
+            expanded_query.insert(expanded_query.end(), std::make_move_iterator(part.begin()),
+                                  std::make_move_iterator(part.end()));
+        }
+        if (expanded_query.size() < n_i) {
+            throw std::invalid_argument("query does not cover the dimension");
+        }
+
+        for (seal::Ciphertext &c : expanded_query) {
+            evaluator.transform_to_ntt_inplace(c);
+        }
+        if (i > 0) {
+            for (seal::Plaintext &p : intermediate_plain) {
+                evaluator.transform_to_ntt_inplace(p, params.parms_id());
+            }
+        }
+
+        product /= n_i;
+        std::vector<seal::Ciphertext> intermediate(product);
+        seal::Ciphertext temp;
+
+        for (uint64_t k = 0; k < product; k++) {
+            for (uint64_t j = 0; j < n_i; j++) {
+                uint64_t index = k + j * product;
+                const seal::Plaintext *plain =
+                    i == 0 ? &plain_at(index) : &intermediate_plain[index];
+
+                if (j == 0) {
+                    evaluator.multiply_plain(expanded_query[0], *plain, intermediate[k]);
+                } else {
+                    evaluator.multiply_plain(expanded_query[j], *plain, temp);
+                    evaluator.add_inplace(intermediate[k], temp);
+                }
+            }
+        }
+
+        for (seal::Ciphertext &c : intermediate) {
+            evaluator.transform_from_ntt_inplace(c);
+        }
+
+        if (i == pir_params.nvec.size() - 1) {
+            return intermediate;
+        }
+
+        std::vector<seal::Plaintext> next(pir_params.expansion_ratio * product,
+                                          seal::Plaintext(N));
+        for (uint64_t rr = 0; rr < product; rr++) {
+            (server->*member(ServerDecompose()))(
+                intermediate[rr], next.data() + rr * pir_params.expansion_ratio, logt);
+        }
+        intermediate_plain = std::move(next);
+        product *= pir_params.expansion_ratio;
+    }
+
+    throw std::logic_error("parameters have no dimensions");
+}
+
+const char SEEDED_MAGIC[8] = {'S', 'E', 'A', 'L', 'P', 'I', 'R', 'Z'};
+const uint8_t SEEDED_QUERY = 0;
+const uint8_t SEEDED_GALOIS_KEY = 1;
+const size_t SEED_SIZE = 32;
+
+// SEAL's default error distribution
+const double NOISE_STANDARD_DEVIATION = 3.19;
+const double NOISE_MAX_DEVIATION = 6 * NOISE_STANDARD_DEVIATION;
+
+// bounds-checked reads from a buffer handed over by the caller
+struct Reader {
+    const uint8_t *data;
+    size_t size;
+    size_t pos;
+
+    size_t remaining() const { return size - pos; }
+
+    void read(void *out, size_t len) {
+        if (len > remaining()) {
+            throw std::invalid_argument("seeded encoding is truncated");
+        }
+        memcpy(out, data + pos, len);
+        pos += len;
+    }
+
+    uint32_t read_u32() {
+        uint32_t value;
+        read(&value, sizeof(value));
+        return value;
+    }
+};
+
+void write_u32(std::ostream &out, uint32_t value) {
+    out.write((const char *)&value, sizeof(value));
+}
+
+// sets c1 of ct to that of the ciphertext at position stream of a seeded encoding
+void expand_c1(seal::Ciphertext &ct, const seal::EncryptionParameters &parms, const uint8_t *seed,
+               uint64_t stream, expand_seed_fn expand) {
+    std::vector<uint64_t> moduli;
+    for (const seal::SmallModulus &modulus : parms.coeff_modulus()) {
+        moduli.push_back(modulus.value());
+    }
+    expand(seed, stream, moduli.data(), moduli.size(), parms.poly_modulus_degree(), ct.data(1));
+}
+
+// poly *= s modulo every coefficient modulus; the secret key is kept in NTT form
+void multiply_by_secret(uint64_t *poly, bool ntt_form, const seal::SecretKey &secret_key,
+                        const seal::SEALContext::ContextData &context_data) {
+    const seal::EncryptionParameters &parms = context_data.parms();
+    size_t n = parms.poly_modulus_degree();
+    for (size_t j = 0; j < parms.coeff_modulus().size(); j++) {
+        uint64_t *p = poly + j * n;
+        const seal::util::SmallNTTTables &tables = context_data.small_ntt_tables()[j];
+        if (!ntt_form) {
+            seal::util::ntt_negacyclic_harvey(p, tables);
+        }
+        seal::util::dyadic_product_coeffmod(p, secret_key.data().data() + j * n, n,
+                                            parms.coeff_modulus()[j], p);
+        if (!ntt_form) {
+            seal::util::inverse_ntt_negacyclic_harvey(p, tables);
+        }
+    }
+}
+
+// Replaces c1 of ct by its seeded value and adjusts c0 so that c0 + c1 * s, and with it the
+// noise, does not change. Only for encryptions under the secret key, like Galois keys: their
+// noise does not depend on s, so the result is distributed like a fresh encryption.
+void reseed(seal::Ciphertext &ct, const seal::SecretKey &secret_key,
+            const seal::SEALContext::ContextData &context_data, const uint8_t *seed,
+            uint64_t stream, expand_seed_fn expand) {
+    const seal::EncryptionParameters &parms = context_data.parms();
+    const std::vector<seal::SmallModulus> &moduli = parms.coeff_modulus();
+    size_t n = parms.poly_modulus_degree();
+
+    std::vector<uint64_t> diff(ct.data(1), ct.data(1) + n * moduli.size());
+    expand_c1(ct, parms, seed, stream, expand);
+    for (size_t j = 0; j < moduli.size(); j++) {
+        seal::util::sub_poly_poly_coeffmod(diff.data() + j * n, ct.data(1) + j * n, n, moduli[j],
+                                           diff.data() + j * n);
+    }
+    multiply_by_secret(diff.data(), ct.is_ntt_form(), secret_key, context_data);
+    for (size_t j = 0; j < moduli.size(); j++) {
+        seal::util::add_poly_poly_coeffmod(ct.data(0) + j * n, diff.data() + j * n, n, moduli[j],
+                                           ct.data(0) + j * n);
+    }
+}
+
+// Overwrites ct with a fresh encryption of plain under the secret key, with c1 taken from the
+// seed. Public-key encryptions cannot be reseeded: part of their noise is a multiple of s.
+void encrypt_seeded(seal::Ciphertext &ct, const seal::Plaintext &plain,
+                    const seal::SecretKey &secret_key,
+                    const seal::SEALContext::ContextData &context_data, const uint8_t *seed,
+                    uint64_t stream, expand_seed_fn expand) {
+    const seal::EncryptionParameters &parms = context_data.parms();
+    if (parms.coeff_modulus().size() != 1 || ct.is_ntt_form()) {
+        throw std::logic_error("seeded queries need a single coefficient modulus");
+    }
+    uint64_t q = parms.coeff_modulus()[0].value();
+    uint64_t delta = q / parms.plain_modulus().value();
+    size_t n = parms.poly_modulus_degree();
+
+    // c0 = -c1 * s + e + delta * m
+    expand_c1(ct, parms, seed, stream, expand);
+    uint64_t *c0 = ct.data(0);
+    std::copy(ct.data(1), ct.data(1) + n, c0);
+    multiply_by_secret(c0, false, secret_key, context_data);
+    seal::util::negate_poly_coeffmod(c0, n, parms.coeff_modulus()[0], c0);
+
+    std::random_device random;
+    std::normal_distribution<double> noise(0, NOISE_STANDARD_DEVIATION);
+    for (size_t i = 0; i < n; i++) {
+        double e;
+        do {
+            e = noise(random);
+        } while (std::fabs(e) > NOISE_MAX_DEVIATION);
+
+        // q is below 2^61 and |e| is tiny, so nothing overflows
+        uint64_t m = i < plain.coeff_count() ? plain[i] : 0;
+        uint64_t scaled = (unsigned __int128)delta * m % q;
+        c0[i] = (c0[i] + scaled + (uint64_t)((int64_t)q + std::llround(e))) % q;
+    }
+}
+
+// serializes ciphertexts whose c1 has been replaced by its seeded value
+string write_seeded(uint8_t kind, const uint8_t *seed, uint32_t bit_count,
+                    const std::vector<std::vector<seal::Ciphertext>> &groups,
+                    const seal::EncryptionParameters &parms) {
+    size_t poly_size = parms.poly_modulus_degree() * parms.coeff_modulus().size();
+    std::ostringstream out;
+    out.write(SEEDED_MAGIC, sizeof(SEEDED_MAGIC));
+    out.put(kind);
+    out.write((const char *)seed, SEED_SIZE);
+    write_u32(out, bit_count);
+    write_u32(out, groups.size());
+    for (const std::vector<seal::Ciphertext> &group : groups) {
+        write_u32(out, group.size());
+    }
+    for (const std::vector<seal::Ciphertext> &group : groups) {
+        for (const seal::Ciphertext &ct : group) {
+            out.put(ct.is_ntt_form() ? 1 : 0);
+            out.write((const char *)ct.data(0), poly_size * sizeof(uint64_t));
+        }
+    }
+    return out.str();
+}
+
+} // namespace
+
+int32_t new_parameters(uint32_t ele_num, uint32_t ele_size, uint32_t N, uint32_t logt, uint32_t d,
+                       void **params, char **error) {
+    return guard(error, [&] {
+        std::unique_ptr<Parameters> param(new Parameters);
+        gen_params(ele_num, ele_size, N, logt, d, param->params, param->pir_params);
+        param->context = seal::SEALContext::Create(param->params);
+        *params = (void *)param.release();
+    });
 }
 
 void delete_parameters(void *params) { delete ((Parameters *)params); }
 
-void *new_pir_client(const void *params) {
-    Parameters *param = (Parameters *)params;
-    PIRClient *client = new PIRClient(param->params, param->pir_params);
-    return (void *)client;
+int32_t get_pir_params(const void *params, uint64_t *n, uint32_t *expansion_ratio, uint32_t *dbc,
+                       uint64_t *nvec, uint32_t d, char **error) {
+    return guard(error, [&] {
+        Parameters *param = (Parameters *)params;
+        if (param->pir_params.nvec.size() != d) {
+            throw std::invalid_argument("dimension count does not match parameters");
+        }
+        *n = param->pir_params.n;
+        *expansion_ratio = param->pir_params.expansion_ratio;
+        *dbc = param->pir_params.dbc;
+        std::copy(param->pir_params.nvec.begin(), param->pir_params.nvec.end(), nvec);
+    });
+}
+
+int32_t new_pir_client(const void *params, void **pir_client, char **error) {
+    return guard(error, [&] {
+        Parameters *param = (Parameters *)params;
+        PIRClient *client = new PIRClient(param->params, param->pir_params);
+        *pir_client = (void *)client;
+    });
 }
 
 void delete_pir_client(void *pir_client) { delete ((PIRClient *)pir_client); }
 
-void *new_pir_server(const void *params) {
-    Parameters *param = (Parameters *)params;
-    PIRServer *server = new PIRServer(param->params, param->pir_params);
-    return (void *)server;
+int32_t new_pir_client_with_secret_key(const void *params, const uint8_t *key, uint32_t key_size,
+                                       void **pir_client, char **error) {
+    return guard(error, [&] {
+        Parameters *param = (Parameters *)params;
+        std::unique_ptr<PIRClient> client(new PIRClient(param->params, param->pir_params));
+        std::shared_ptr<seal::SEALContext> context = client.get()->*member(ClientContext());
+
+        seal::SecretKey secret_key;
+        SpanInBuf buf(key, key_size);
+        std::istream input(&buf);
+        secret_key.load(context, input);
+
+        // everything PIRClient derived from the secret key it generated
+        std::unique_ptr<seal::KeyGenerator> keygen(new seal::KeyGenerator(context, secret_key));
+        client.get()->*member(ClientEncryptor()) =
+            std::unique_ptr<seal::Encryptor>(new seal::Encryptor(context, keygen->public_key()));
+        client.get()->*member(ClientDecryptor()) =
+            std::unique_ptr<seal::Decryptor>(new seal::Decryptor(context, secret_key));
+        client.get()->*member(ClientKeygen()) = std::move(keygen);
+
+        *pir_client = (void *)client.release();
+    });
+}
+
+int32_t new_pir_server(const void *params, void **pir_server, char **error) {
+    return guard(error, [&] {
+        Parameters *param = (Parameters *)params;
+        PIRServer *server = new PIRServer(param->params, param->pir_params);
+        *pir_server = (void *)server;
+    });
 }
 
 void delete_pir_server(void *pir_server) { delete ((PIRServer *)pir_server); }
 
-uint8_t *get_galois_key(const void *pir_client, uint32_t *key_size) {
-    PIRClient *client = (PIRClient *)pir_client;
-    seal::GaloisKeys galois = client->generate_galois_keys();
-    string ser = serialize_galoiskeys(galois);
+int32_t get_seeded_galois_key(const void *pir_client, const void *params, const uint8_t *seed,
+                              expand_seed_fn expand, uint8_t **key, uint32_t *key_size,
+                              char **error) {
+    return guard(error, [&] {
+        PIRClient *client = (PIRClient *)pir_client;
+        Parameters *param = (Parameters *)params;
+        const seal::SecretKey &secret_key = (client->*member(ClientKeygen()))->secret_key();
+        auto context_data = param->context->context_data(param->params.parms_id());
 
-    uint32_t size = ser.size();
-    uint8_t *out = (uint8_t *)malloc(size);
-    memcpy(out, ser.data(), size);
-    *key_size = size;
-    return out;
+        seal::GaloisKeys galois = client->generate_galois_keys();
+        uint64_t stream = 0;
+        for (std::vector<seal::Ciphertext> &keys : galois.data()) {
+            for (seal::Ciphertext &ct : keys) {
+                reseed(ct, secret_key, *context_data, seed, stream++, expand);
+            }
+        }
+
+        string ser = write_seeded(SEEDED_GALOIS_KEY, seed, galois.decomposition_bit_count(),
+                                  galois.data(), param->params);
+        *key = copy_bytes(ser, key_size);
+    });
 }
 
-void set_galois_key(void *pir_server, const uint8_t *galois_key, uint32_t key_size,
-                    uint32_t client_id) {
-    PIRServer *server = (PIRServer *)pir_server;
-    string gal_str = string((const char *)galois_key, key_size);
-    seal::GaloisKeys *galois = deserialize_galoiskeys(gal_str);
-    server->set_galois_key(client_id, *galois);
-    delete galois;
+int32_t get_secret_key(const void *pir_client, uint8_t *key, uint32_t *key_size, char **error) {
+    return guard(error, [&] {
+        const PIRClient *client = (const PIRClient *)pir_client;
+        SpanOutBuf buf(key, key == nullptr ? 0 : *key_size);
+        std::ostream output(&buf);
+        (client->*member(ClientKeygen()))->secret_key().save(output);
+        if (key != nullptr && buf.count() > *key_size) {
+            throw std::logic_error("key cannot hold the secret key");
+        }
+        *key_size = buf.count();
+    });
 }
 
-uint32_t get_fv_index(const void *pir_client, uint32_t ele_index, uint32_t ele_size) {
-    PIRClient *client = (PIRClient *)pir_client;
-    return client->get_fv_index(ele_index, ele_size);
+int32_t set_galois_key(void *pir_server, const uint8_t *galois_key, uint32_t key_size,
+                       uint32_t client_id, char **error) {
+    return guard(error, [&] {
+        PIRServer *server = (PIRServer *)pir_server;
+        string gal_str = string((const char *)galois_key, key_size);
+        std::unique_ptr<seal::GaloisKeys> galois(deserialize_galoiskeys(gal_str));
+        server->set_galois_key(client_id, *galois);
+    });
 }
 
-uint32_t get_fv_offset(const void *pir_client, uint32_t ele_index, uint32_t ele_size) {
-    PIRClient *client = (PIRClient *)pir_client;
-    return client->get_fv_offset(ele_index, ele_size);
+int32_t remove_galois_key(void *pir_server, uint32_t client_id, char **error) {
+    return guard(error, [&] {
+        PIRServer *server = (PIRServer *)pir_server;
+        (server->*member(ServerGaloisKeys())).erase(client_id);
+    });
 }
 
-uint8_t *generate_query(const void *pir_client, uint32_t index, uint32_t *query_size,
-                        uint32_t *query_num) {
-    PIRClient *client = (PIRClient *)pir_client;
-    PirQuery query = client->generate_query(index);
-    *query_num = query[0].size();
-    string ser = serialize_query(query);
+int32_t copy_galois_keys(const void *from, void *to, char **error) {
+    return guard(error, [&] {
+        const PIRServer *source = (const PIRServer *)from;
+        PIRServer *target = (PIRServer *)to;
+        target->*member(ServerGaloisKeys()) = source->*member(ServerGaloisKeys());
+    });
+}
 
-    uint32_t size = ser.size();
-    uint8_t *out = (uint8_t *)malloc(size);
-    memcpy(out, ser.data(), size);
-    *query_size = size;
-    return out;
+int32_t get_fv_index(const void *pir_client, uint32_t ele_index, uint32_t ele_size,
+                     uint32_t *fv_index, char **error) {
+    return guard(error, [&] {
+        PIRClient *client = (PIRClient *)pir_client;
+        *fv_index = client->get_fv_index(ele_index, ele_size);
+    });
 }
 
-uint8_t *generate_reply(const void *pir_server, const void *params, const uint8_t *query, uint32_t query_size,
-                        uint32_t query_num, uint32_t *reply_size, uint32_t *reply_num,
-                        uint32_t client_id) {
+int32_t get_fv_offset(const void *pir_client, uint32_t ele_index, uint32_t ele_size,
+                      uint32_t *fv_offset, char **error) {
+    return guard(error, [&] {
+        PIRClient *client = (PIRClient *)pir_client;
+        *fv_offset = client->get_fv_offset(ele_index, ele_size);
+    });
+}
 
-    PIRServer *server = (PIRServer *)pir_server;
-    Parameters *param = (Parameters *)params;
-    string query_str = string((const char *)query, query_size);
+int32_t generate_query(const void *pir_client, uint32_t index, uint8_t **query,
+                       uint32_t *query_size, uint32_t *query_num, char **error) {
+    return guard(error, [&] {
+        PIRClient *client = (PIRClient *)pir_client;
+        PirQuery pir_query = client->generate_query(index);
+        string ser = serialize_query(pir_query);
+        *query = copy_bytes(ser, query_size);
+        *query_num = pir_query[0].size();
+    });
+}
 
-    PirQuery query_des = deserialize_query(param->pir_params.d, query_num, query_str, CIPHER_SIZE);
-    PirReply reply = server->generate_reply(query_des, client_id);
-    *reply_num = reply.size();
+int32_t generate_seeded_query(const void *pir_client, const void *params, uint32_t index,
+                              const uint8_t *seed, expand_seed_fn expand, uint8_t **query,
+                              uint32_t *query_size, uint32_t *query_num, char **error) {
+    return guard(error, [&] {
+        PIRClient *client = (PIRClient *)pir_client;
+        Parameters *param = (Parameters *)params;
+        const seal::SecretKey &secret_key = (client->*member(ClientKeygen()))->secret_key();
+        auto context_data = param->context->context_data(param->params.parms_id());
 
-    string ser = serialize_ciphertexts(reply);
-    uint32_t size = ser.size();
-    uint8_t *out = (uint8_t *)malloc(size);
-    memcpy(out, ser.data(), size);
-    *reply_size = size;
-    return out;
+        // SealPIR builds the plaintexts of a query; they are recovered from its ciphertexts
+        // rather than duplicating how
+        PirQuery pir_query = client->generate_query(index);
+        uint64_t stream = 0;
+        for (std::vector<seal::Ciphertext> &dimension : pir_query) {
+            for (seal::Ciphertext &ct : dimension) {
+                seal::Plaintext plain = client->decrypt(ct);
+                encrypt_seeded(ct, plain, secret_key, *context_data, seed, stream++, expand);
+            }
+        }
+
+        string ser = write_seeded(SEEDED_QUERY, seed, 0, pir_query, param->params);
+        *query = copy_bytes(ser, query_size);
+        *query_num = pir_query[0].size();
+    });
 }
 
-void set_database(void *pir_server, const uint8_t *database, uint32_t ele_num, uint32_t ele_size) {
-    PIRServer *server = (PIRServer *)pir_server;
-    server->set_database(database, ele_num, ele_size);
+int32_t expand_seeded(const void *params, uint8_t kind, const uint8_t *data, uint32_t size,
+                      expand_seed_fn expand, uint8_t **expanded, uint32_t *expanded_size,
+                      uint32_t *count, char **error) {
+    return guard(error, [&] {
+        Parameters *param = (Parameters *)params;
+        const seal::EncryptionParameters &parms = param->params;
+        const std::vector<seal::SmallModulus> &moduli = parms.coeff_modulus();
+        size_t n = parms.poly_modulus_degree();
+        Reader in{data, size, 0};
+
+        char magic[sizeof(SEEDED_MAGIC)];
+        in.read(magic, sizeof(magic));
+        if (memcmp(magic, SEEDED_MAGIC, sizeof(magic)) != 0) {
+            throw std::invalid_argument("not a seeded encoding");
+        }
+        uint8_t stored_kind;
+        in.read(&stored_kind, 1);
+        if (stored_kind != kind) {
+            throw std::invalid_argument("seeded encoding holds the wrong kind of ciphertexts");
+        }
+        uint8_t seed[SEED_SIZE];
+        in.read(seed, SEED_SIZE);
+        uint32_t bit_count = in.read_u32();
+
+        uint32_t num_groups = in.read_u32();
+        if (num_groups > in.remaining() / sizeof(uint32_t)) {
+            throw std::invalid_argument("seeded encoding is truncated");
+        }
+        std::vector<uint32_t> group_sizes(num_groups);
+        uint64_t total = 0;
+        for (uint32_t &group_size : group_sizes) {
+            group_size = in.read_u32();
+            total += group_size;
+        }
+        size_t ciphertext_size = 1 + n * moduli.size() * sizeof(uint64_t);
+        if (total > in.remaining() / ciphertext_size) {
+            throw std::invalid_argument("seeded encoding is truncated");
+        }
+        std::vector<std::vector<seal::Ciphertext>> groups(num_groups);
+        for (uint32_t g = 0; g < num_groups; g++) {
+            groups[g].resize(group_sizes[g]);
+        }
+
+        uint64_t stream = 0;
+        for (std::vector<seal::Ciphertext> &group : groups) {
+            for (seal::Ciphertext &ct : group) {
+                ct.resize(param->context, parms.parms_id(), 2);
+                uint8_t ntt_form;
+                in.read(&ntt_form, 1);
+                ct.is_ntt_form() = ntt_form != 0;
+                in.read(ct.data(0), n * moduli.size() * sizeof(uint64_t));
+                for (size_t j = 0; j < moduli.size(); j++) {
+                    const uint64_t *c0 = ct.data(0) + j * n;
+                    if (std::any_of(c0, c0 + n, [&](uint64_t c) { return c >= moduli[j].value(); })) {
+                        throw std::invalid_argument("coefficient is not reduced");
+                    }
+                }
+                expand_c1(ct, parms, seed, stream++, expand);
+            }
+        }
+        if (in.remaining() != 0) {
+            throw std::invalid_argument("seeded encoding has trailing data");
+        }
+
+        string ser;
+        if (kind == SEEDED_QUERY) {
+            ser = serialize_query(groups);
+        } else if (kind == SEEDED_GALOIS_KEY) {
+            seal::GaloisKeys galois;
+            galois.data() = std::move(groups);
+            galois.parms_id() = parms.parms_id();
+            galois.*member(GaloisKeysBitCount()) = bit_count;
+            ser = serialize_galoiskeys(galois);
+        } else {
+            throw std::invalid_argument("unknown kind of seeded encoding");
+        }
+        *expanded = copy_bytes(ser, expanded_size);
+        *count = stream;
+    });
+}
+
+// May run concurrently on the same server, which the Rust side relies on to share a
+// PirServer between threads. It only reads server state provided that the database has
+// been preprocessed: unlike PIRServer::generate_reply, it never transforms the database to
+// NTT form in place, and it looks the Galois key up without inserting into the key map.
+// SEAL evaluator operations allocate from the global memory pool, which is thread-safe.
+int32_t generate_reply(const void *pir_server, const void *params, const uint8_t *query,
+                       uint32_t query_size, uint32_t query_num, uint8_t **reply,
+                       uint32_t *reply_size, uint32_t *reply_num, const void *key_server,
+                       uint32_t client_id, char **error) {
+    return guard(error, [&] {
+        PIRServer *server = (PIRServer *)pir_server;
+        Parameters *param = (Parameters *)params;
+        string query_str = string((const char *)query, query_size);
+
+        PirQuery query_des =
+            deserialize_query(param->pir_params.d, query_num, query_str, CIPHER_SIZE);
+        const Database &db = preprocessed_db(server);
+        const seal::GaloisKeys &galkey = galois_key((const PIRServer *)key_server, client_id);
+        PirReply pir_reply = generate_reply_from(
+            server, param, query_des, galkey, db.size(),
+            [&](uint64_t index) -> const seal::Plaintext & { return db[index]; });
+
+        string ser = serialize_ciphertexts(pir_reply);
+        *reply = copy_bytes(ser, reply_size);
+        *reply_num = pir_reply.size();
+    });
 }
 
-void update_database(void *pir_server, const uint8_t *database, uint32_t ele_num, uint32_t ele_size, uint32_t ele_index) {
-    PIRServer *server = (PIRServer *)pir_server;
-    server->update_database(database, ele_num, ele_size, ele_index);
+int32_t set_database(void *pir_server, const uint8_t *database, uint32_t ele_num, uint32_t ele_size,
+                     char **error) {
+    return guard(error, [&] {
+        PIRServer *server = (PIRServer *)pir_server;
+        server->set_database(database, ele_num, ele_size);
+    });
 }
 
-void preprocess_db(void *pir_server) {
-    PIRServer *server = (PIRServer *)pir_server;
-    server->preprocess_database();
+int32_t preprocess_db(void *pir_server, char **error) {
+    return guard(error, [&] {
+        PIRServer *server = (PIRServer *)pir_server;
+        server->preprocess_database();
+    });
 }
 
-uint8_t *decode_reply(const void *pir_client, const void *params, const uint8_t *reply,
-                      uint32_t reply_size, uint32_t reply_num, uint32_t *size) {
+int32_t decode_reply(const void *pir_client, const void *params, const uint8_t *reply,
+                     uint32_t reply_size, uint32_t reply_num, uint8_t *result, uint32_t *size,
+                     uint32_t *budgets, char **error) {
+    return guard(error, [&] {
+        PIRClient *client = (PIRClient *)pir_client;
+        Parameters *param = (Parameters *)params;
 
-    PIRClient *client = (PIRClient *)pir_client;
-    Parameters *param = (Parameters *)params;
+        string reply_str = string((const char *)reply, reply_size);
+
+        PirReply reply_res = deserialize_ciphertexts(reply_num, reply_str, CIPHER_SIZE);
+        seal::Plaintext plain = decode_layers(client, param->pir_params, reply_res, budgets);
+
+        uint32_t logtp = ceil(log2(param->params.plain_modulus().value() - 1));
+        uint32_t N = param->params.poly_modulus_degree();
+
+        if (*size < (N * logtp) / 8) {
+            throw std::logic_error("result cannot hold the decoded plaintext");
+        }
+        coeffs_to_bytes(logtp, plain, result, (N * logtp) / 8);
+        *size = (N * logtp) / 8;
+    });
+}
+
+int32_t get_preprocessed_db(const void *pir_server, uint64_t *num_plaintexts,
+                            uint64_t *coeff_count, char **error) {
+    return guard(error, [&] {
+        Database &db = preprocessed_db((const PIRServer *)pir_server);
+        *num_plaintexts = db.size();
+        *coeff_count = db.empty() ? 0 : db[0].coeff_count();
+    });
+}
+
+int32_t get_preprocessed_plaintext(const void *pir_server, uint64_t index,
+                                   const uint64_t **coeffs, char **error) {
+    return guard(error, [&] {
+        Database &db = preprocessed_db((const PIRServer *)pir_server);
+        if (index >= db.size()) {
+            throw std::invalid_argument("plaintext index out of range");
+        }
+        *coeffs = db[index].data();
+    });
+}
+
+int32_t new_preprocessed_db(void *pir_server, uint64_t num_plaintexts, char **error) {
+    return guard(error, [&] {
+        PIRServer *server = (PIRServer *)pir_server;
+        server->*member(ServerDb()) = std::make_unique<Database>(num_plaintexts);
+        server->*member(ServerDbPreprocessed()) = true;
+    });
+}
+
+int32_t set_preprocessed_plaintext(void *pir_server, const void *params, uint64_t index,
+                                   const uint64_t *coeffs, uint64_t coeff_count, char **error) {
+    return guard(error, [&] {
+        Parameters *param = (Parameters *)params;
+        Database &db = preprocessed_db((const PIRServer *)pir_server);
+        if (index >= db.size()) {
+            throw std::invalid_argument("plaintext index out of range");
+        }
+
+        // one NTT-form coefficient per modulus for each of the N coefficients
+        uint64_t expected =
+            param->params.poly_modulus_degree() * param->params.coeff_modulus().size();
+        if (coeff_count != expected) {
+            throw std::invalid_argument("wrong number of coefficients");
+        }
+
+        seal::Plaintext plain;
+        plain.resize(coeff_count);
+        std::copy(coeffs, coeffs + coeff_count, plain.data());
+        plain.parms_id() = param->params.parms_id();
+        db[index] = std::move(plain);
+    });
+}
+
+int32_t generate_reply_mapped(const void *pir_server, const void *params, const uint8_t *db,
+                              uint64_t num_plaintexts, uint64_t coeff_count,
+                              const uint8_t *query, uint32_t query_size, uint32_t query_num,
+                              uint8_t **reply, uint32_t *reply_size, uint32_t *reply_num,
+                              const void *key_server, uint32_t client_id, char **error) {
+    return guard(error, [&] {
+        PIRServer *server = (PIRServer *)pir_server;
+        Parameters *param = (Parameters *)params;
+        string query_str = string((const char *)query, query_size);
+
+        PirQuery query_des =
+            deserialize_query(param->pir_params.d, query_num, query_str, CIPHER_SIZE);
+        if (coeff_count != param->params.poly_modulus_degree() *
+                               param->params.coeff_modulus().size()) {
+            throw std::invalid_argument("wrong number of coefficients");
+        }
+
+        // each plaintext is copied from db into a scratch plaintext when it is needed
+        seal::Plaintext mapped(coeff_count);
+        mapped.parms_id() = param->params.parms_id();
+        const seal::GaloisKeys &galkey = galois_key((const PIRServer *)key_server, client_id);
+        PirReply pir_reply = generate_reply_from(
+            server, param, query_des, galkey, num_plaintexts,
+            [&](uint64_t index) -> const seal::Plaintext & {
+                memcpy(mapped.data(), db + index * coeff_count * sizeof(uint64_t),
+                       coeff_count * sizeof(uint64_t));
+                return mapped;
+            });
+
+        string ser = serialize_ciphertexts(pir_reply);
+        *reply = copy_bytes(ser, reply_size);
+        *reply_num = pir_reply.size();
+    });
+}
+
+int32_t set_plaintext_elements(void *pir_server, const void *params, uint64_t index,
+                               const uint8_t *bytes, uint64_t size, char **error) {
+    return guard(error, [&] {
+        PIRServer *server = (PIRServer *)pir_server;
+        Parameters *param = (Parameters *)params;
+        Database &db = preprocessed_db(server);
+        if (index >= db.size()) {
+            throw std::invalid_argument("plaintext index out of range");
+        }
+
+        uint32_t logt = floor(log2(param->params.plain_modulus().value()));
+        uint64_t N = param->params.poly_modulus_degree();
+
+        vector<uint64_t> coefficients;
+        if (size > 0) {
+            coefficients = bytes_to_coeffs(logt, bytes, size);
+        }
+        if (coefficients.size() > N) {
+            throw std::invalid_argument("elements do not fit in one plaintext");
+        }
+        // set_database pads with 1s
+        coefficients.resize(N, 1);
+
+        seal::Plaintext plain;
+        vector_to_plaintext(coefficients, plain);
+        (server->*member(ServerEvaluator()))
+            ->transform_to_ntt_inplace(plain, param->params.parms_id());
+        db[index] = std::move(plain);
+    });
+}
 
-    string reply_str = string((const char *)reply, reply_size);
+int32_t update_plaintext_elements(void *pir_server, const void *params, uint64_t index,
+                                  const uint64_t *offsets, uint64_t count, const uint8_t *bytes,
+                                  uint64_t ele_size, char **error) {
+    return guard(error, [&] {
+        PIRServer *server = (PIRServer *)pir_server;
+        Parameters *param = (Parameters *)params;
+        Database &db = preprocessed_db(server);
+        if (index >= db.size()) {
+            throw std::invalid_argument("plaintext index out of range");
+        }
 
-    PirReply reply_res = deserialize_ciphertexts(reply_num, reply_str, CIPHER_SIZE);
-    seal::Plaintext result = client->decode_reply(reply_res);
+        uint32_t logt = floor(log2(param->params.plain_modulus().value()));
+        uint64_t N = param->params.poly_modulus_degree();
+        uint64_t size = (N * logt) / 8;
 
-    uint32_t logtp = ceil(log2(param->params.plain_modulus().value() - 1));
-    uint32_t N = param->params.poly_modulus_degree();
+        // The coefficients are smaller than the plain modulus, hence than every coefficient
+        // modulus, so undoing the NTT modulo the first one recovers them exactly.
+        seal::Plaintext plain(N);
+        std::copy(db[index].data(), db[index].data() + N, plain.data());
+        auto context_data = param->context->context_data(param->params.parms_id());
+        seal::util::inverse_ntt_negacyclic_harvey(plain.data(),
+                                                  context_data->small_ntt_tables()[0]);
 
-    uint8_t *elems = (uint8_t *)malloc((N * logtp) / 8);
-    coeffs_to_bytes(logtp, result, elems, (N * logtp) / 8);
+        // N coefficients of logt bits are exactly size bytes, padding included, so the
+        // conversion round-trips
+        std::vector<uint8_t> packed(size);
+        coeffs_to_bytes(logt, plain, packed.data(), size);
+        for (uint64_t i = 0; i < count; i++) {
+            if (offsets[i] > size || ele_size > size - offsets[i]) {
+                throw std::invalid_argument("element offset out of range");
+            }
+            memcpy(packed.data() + offsets[i], bytes + i * ele_size, ele_size);
+        }
 
-    *size = (N * logtp) / 8;
-    return elems;
+        vector_to_plaintext(bytes_to_coeffs(logt, packed.data(), size), plain);
+        (server->*member(ServerEvaluator()))
+            ->transform_to_ntt_inplace(plain, param->params.parms_id());
+        db[index] = std::move(plain);
+    });
 }
@@ -5,72 +5,184 @@
 #include "pir_client.hpp"
 #include "pir_server.hpp"
 
+// status codes returned by every fallible binding
+#define PIR_OK 0
+#define PIR_INVALID_ARGUMENT 1 // std::invalid_argument (e.g., malformed serialized data)
+#define PIR_LOGIC_ERROR 2      // any other std::logic_error
+#define PIR_ERROR 3            // any other exception
+
 extern "C" {
 
 struct Parameters {
     seal::EncryptionParameters params;
     PirParams pir_params;
+    std::shared_ptr<seal::SEALContext> context; // for NTTs and seeded encodings
     Parameters(): params(seal::EncryptionParameters(seal::scheme_type::BFV)) {};
 };
 
+// Every function below that returns int32_t catches all C++ exceptions and returns one of
+// the PIR_* status codes. On failure, *error points to a malloc'ed, NUL-terminated message
+// that the caller must free; on success it is set to NULL and output arguments are valid.
+
 // returns a pointer to SealPIR's parameters
-void *new_parameters(uint32_t ele_num, uint32_t ele_size, uint32_t N, uint32_t logt, uint32_t d);
+int32_t new_parameters(uint32_t ele_num, uint32_t ele_size, uint32_t N, uint32_t logt, uint32_t d,
+                       void **params, char **error);
 void delete_parameters(void *params);
 
+// copies the number of FV plaintexts, the expansion ratio, the decomposition bit count of
+// Galois keys and the size of each of the d dimensions (nvec must have room for d entries)
+int32_t get_pir_params(const void *params, uint64_t *n, uint32_t *expansion_ratio, uint32_t *dbc,
+                       uint64_t *nvec, uint32_t d, char **error);
+
 // Client operations
 
 // returns a pointer to a PirClient object
-void *new_pir_client(const void *params);
+int32_t new_pir_client(const void *params, void **pir_client, char **error);
 void delete_pir_client(void *pir_client);
 
 // returns index of FV plaintext given the index of an element
-uint32_t get_fv_index(const void *pir_client, uint32_t ele_index, uint32_t ele_size);
+int32_t get_fv_index(const void *pir_client, uint32_t ele_index, uint32_t ele_size,
+                     uint32_t *fv_index, char **error);
 
 // returns the offset within an FV plaintext for the given element index
-uint32_t get_fv_offset(const void *pir_client, uint32_t ele_index, uint32_t ele_size);
-
-// get the serialized representation of a galois key
-uint8_t *get_galois_key(const void *pir_client, uint32_t *key_size);
+int32_t get_fv_offset(const void *pir_client, uint32_t ele_index, uint32_t ele_size,
+                      uint32_t *fv_offset, char **error);
+
+// Seeded encodings replace the uniformly random second component (c1) of every ciphertext
+// by a 32-byte seed, which roughly halves their size. Layout, in native byte order:
+//   magic "SEALPIRZ", kind (uint8_t: 0 query, 1 Galois key), seed (32 bytes)
+//   decomposition bit count (uint32_t, 0 for queries)
+//   number of groups (uint32_t), then the number of ciphertexts in each group (uint32_t)
+//   for every ciphertext: NTT flag (uint8_t), then the coefficients of c0 (uint64_t each)
+// c1 of the k-th ciphertext is expand(seed, k, ...). Groups are the dimensions of a query,
+// or the keys of each Galois element.
+
+// fills out with coeff_count coefficients uniformly distributed modulo each of the
+// moduli_count moduli, modulus after modulus, as a deterministic function of seed and stream
+typedef void (*expand_seed_fn)(const uint8_t *seed, uint64_t stream, const uint64_t *moduli,
+                               uint32_t moduli_count, uint64_t coeff_count, uint64_t *out);
+
+// generates the client's Galois keys in seeded form
+int32_t get_seeded_galois_key(const void *pir_client, const void *params, const uint8_t *seed,
+                              expand_seed_fn expand, uint8_t **key, uint32_t *key_size,
+                              char **error);
+
+// writes the serialized secret key of the client to key, which the caller has to wipe; no
+// other copy is made
+// key_size: room in key on input, size of the serialized key on output; with key == NULL,
+//           only the size is returned
+int32_t get_secret_key(const void *pir_client, uint8_t *key, uint32_t *key_size, char **error);
+
+// like new_pir_client, but the client uses the given serialized secret key instead of a
+// fresh one
+int32_t new_pir_client_with_secret_key(const void *params, const uint8_t *key, uint32_t key_size,
+                                       void **pir_client, char **error);
 
 // get the serialized version of a PIR query for the given index
 // num: number of ciphertexts making up the query
 // query_size: size in bytes
-uint8_t *generate_query(const void *pir_client, uint32_t index, uint32_t *query_size,
-                        uint32_t *query_num);
+int32_t generate_query(const void *pir_client, uint32_t index, uint8_t **query,
+                       uint32_t *query_size, uint32_t *query_num, char **error);
 
-// decodes the given reply and returns a pointer to the N coefficients
+// like generate_query, but the query is in seeded form; every ciphertext is a fresh
+// encryption under the secret key
+int32_t generate_seeded_query(const void *pir_client, const void *params, uint32_t index,
+                              const uint8_t *seed, expand_seed_fn expand, uint8_t **query,
+                              uint32_t *query_size, uint32_t *query_num, char **error);
+
+// decodes the given reply and writes the bytes of its N coefficients to result
 // reply_num: number of ciphertexts making up the query
 // reply_size: size in bytes of the reply
-// size: size in bytes of the decoded elements
-uint8_t *decode_reply(const void *pir_client, const void *param, const uint8_t *reply,
-                      uint32_t reply_size, uint32_t reply_num, uint32_t *size);
+// size: room in result on input, size in bytes of the decoded elements on output
+// budgets: room for reply_num entries; receives the remaining invariant noise budget (in bits)
+//          of each reply ciphertext and of the ciphertexts it is composed into. 0 means the
+//          decoded elements are garbage
+int32_t decode_reply(const void *pir_client, const void *param, const uint8_t *reply,
+                     uint32_t reply_size, uint32_t reply_num, uint8_t *result, uint32_t *size,
+                     uint32_t *budgets, char **error);
+
+// expands a seeded encoding of the given kind into SealPIR's serialization of the full
+// ciphertexts (serialize_query or serialize_galoiskeys); count is the number of ciphertexts
+// it holds
+int32_t expand_seeded(const void *params, uint8_t kind, const uint8_t *data, uint32_t size,
+                      expand_seed_fn expand, uint8_t **expanded, uint32_t *expanded_size,
+                      uint32_t *count, char **error);
 
 // Server operations
 
 // returns a pointer to a PirServer object
-void *new_pir_server(const void *params);
+int32_t new_pir_server(const void *params, void **pir_server, char **error);
 void delete_pir_server(void *pir_server);
 
 // deserializes the galois key and configures it for the given client
-void set_galois_key(void *pir_server, const uint8_t *galois_key, uint32_t key_size,
-                    uint32_t client_id);
+int32_t set_galois_key(void *pir_server, const uint8_t *galois_key, uint32_t key_size,
+                       uint32_t client_id, char **error);
 
-// sets the existing database
-void set_database(void *pir_server, const uint8_t *database, uint32_t ele_num, uint32_t ele_size);
+// forgets the galois key of the given client, if any
+int32_t remove_galois_key(void *pir_server, uint32_t client_id, char **error);
 
-// update the existing database
-void update_database(void *pir_server, const uint8_t *database, uint32_t ele_num, uint32_t ele_size, uint32_t ele_index);
+// replaces the galois keys of to with copies of those registered with from; both servers
+// must have the same SEAL parameters
+int32_t copy_galois_keys(const void *from, void *to, char **error);
+
+// sets the existing database
+int32_t set_database(void *pir_server, const uint8_t *database, uint32_t ele_num, uint32_t ele_size,
+                     char **error);
 
 // preprocesses the database
-void preprocess_db(void *pir_server);
+int32_t preprocess_db(void *pir_server, char **error);
 
 // generates a reply for the given client
 // query_size: bytes of query
 // query_num: number of ciphertexts
 // reply_num: number of ciphertexts
 // reply_size: bytes of reply
-uint8_t *generate_reply(const void *pir_server, const void *params, const uint8_t *query, uint32_t query_size,
-                        uint32_t query_num, uint32_t *reply_size, uint32_t *reply_num,
-                        uint32_t client_id);
+// key_server: the server the Galois key of client_id is registered with, pir_server itself
+//             or another server with the same parameters
+int32_t generate_reply(const void *pir_server, const void *params, const uint8_t *query,
+                       uint32_t query_size, uint32_t query_num, uint8_t **reply,
+                       uint32_t *reply_size, uint32_t *reply_num, const void *key_server,
+                       uint32_t client_id, char **error);
+
+// Access to the preprocessed (NTT form) database, used to persist it
+
+// returns the number of plaintexts of the preprocessed database and the number of
+// coefficients of each; fails if the database has not been preprocessed
+int32_t get_preprocessed_db(const void *pir_server, uint64_t *num_plaintexts,
+                            uint64_t *coeff_count, char **error);
+
+// points coeffs to the coefficients of the given plaintext; they remain valid until the
+// database is modified
+int32_t get_preprocessed_plaintext(const void *pir_server, uint64_t index,
+                                   const uint64_t **coeffs, char **error);
+
+// replaces the database with num_plaintexts empty plaintexts that are considered
+// preprocessed; each must then be set with set_preprocessed_plaintext
+int32_t new_preprocessed_db(void *pir_server, uint64_t num_plaintexts, char **error);
+
+// sets the given plaintext of the database to coeff_count coefficients in NTT form
+int32_t set_preprocessed_plaintext(void *pir_server, const void *params, uint64_t index,
+                                   const uint64_t *coeffs, uint64_t coeff_count, char **error);
+
+// packs size bytes of consecutive elements into the given plaintext of the database the
+// way set_database does (size 0 gives a padding plaintext) and transforms it to NTT form
+int32_t set_plaintext_elements(void *pir_server, const void *params, uint64_t index,
+                               const uint8_t *bytes, uint64_t size, char **error);
+
+// overwrites count elements of ele_size bytes each, taken consecutively from bytes, at the
+// given byte offsets of the elements packed into the given preprocessed plaintext; only
+// that plaintext is transformed back from and again to NTT form
+int32_t update_plaintext_elements(void *pir_server, const void *params, uint64_t index,
+                                  const uint64_t *offsets, uint64_t count, const uint8_t *bytes,
+                                  uint64_t ele_size, char **error);
+
+// like generate_reply, but reads the preprocessed database from db instead of the server:
+// num_plaintexts plaintexts of coeff_count little-endian NTT-form coefficients each, which
+// need not be aligned (e.g., a memory-mapped file); they are copied one at a time
+int32_t generate_reply_mapped(const void *pir_server, const void *params, const uint8_t *db,
+                              uint64_t num_plaintexts, uint64_t coeff_count,
+                              const uint8_t *query, uint32_t query_size, uint32_t query_num,
+                              uint8_t **reply, uint32_t *reply_size, uint32_t *reply_num,
+                              const void *key_server, uint32_t client_id, char **error);
 }
 #endif
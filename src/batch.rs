@@ -0,0 +1,415 @@
+//! Batch PIR with probabilistic batch codes.
+//!
+//! Every element is replicated into `NUM_HASHES` of the `num_buckets` buckets chosen by
+//! public hash functions, and each bucket is served as its own PIR database. To retrieve
+//! up to `batch_size` elements, the client cuckoo-hashes the requested indices so that
+//! each lands in a distinct bucket and sends one query per bucket (a dummy one for
+//! buckets it does not need), which the server answers in a single `gen_replies` call.
+//! The server stores `NUM_HASHES` copies of the database but processes each copy once
+//! per batch instead of processing the whole database once per element.
+
+use bytemuck::Pod;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::mem;
+
+use super::client::PirClient;
+use super::hash;
+use super::params::PirParameters;
+use super::server::PirServer;
+use super::{ClientHandle, Error, PirQuery, PirReply, Result};
+
+/// Number of buckets each element is replicated into.
+pub const NUM_HASHES: usize = 3;
+
+// evictions attempted before cuckoo insertion gives up
+const MAX_EVICTIONS: usize = 500;
+
+/// Public parameters of a batch PIR database. Both sides must use the same values,
+/// including the seed of the bucket hash functions.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BatchParameters {
+    ele_num: u32,
+    ele_size: u32,
+    batch_size: u32,
+    seed: u64,
+    poly_degree: u32,
+    log_plain_mod: u32,
+    d: u32,
+}
+
+impl BatchParameters {
+    /// Parameters for retrieving up to `batch_size` of `ele_num` elements of `ele_size`
+    /// bytes per batch. `poly_degree`, `log_plain_mod` and `d` configure the PIR database
+    /// of each bucket.
+    pub fn new(
+        ele_num: u32,
+        ele_size: u32,
+        batch_size: u32,
+        poly_degree: u32,
+        log_plain_mod: u32,
+        d: u32,
+    ) -> BatchParameters {
+        BatchParameters {
+            ele_num,
+            ele_size,
+            batch_size,
+            seed: rand::thread_rng().gen(),
+            poly_degree,
+            log_plain_mod,
+            d,
+        }
+    }
+
+    /// Replaces the randomly chosen seed of the bucket hash functions.
+    pub fn with_seed(mut self, seed: u64) -> BatchParameters {
+        self.seed = seed;
+        self
+    }
+
+    pub fn ele_num(&self) -> u32 {
+        self.ele_num
+    }
+
+    pub fn ele_size(&self) -> u32 {
+        self.ele_size
+    }
+
+    pub fn batch_size(&self) -> u32 {
+        self.batch_size
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// 1.5 buckets per batch element, and at least one per hash function.
+    pub fn num_buckets(&self) -> u32 {
+        (self.batch_size as u64 * 3)
+            .div_ceil(2)
+            .max(NUM_HASHES as u64) as u32
+    }
+
+    // buckets element `index` is replicated into
+    fn candidate_buckets(&self, index: u32) -> Vec<u32> {
+        hash::distinct_slots(
+            self.seed,
+            &index.to_le_bytes(),
+            NUM_HASHES,
+            self.num_buckets(),
+        )
+    }
+}
+
+// which elements each bucket holds, plus the PIR parameters all buckets share
+struct Layout {
+    buckets: Vec<Vec<u32>>,
+    params: PirParameters,
+}
+
+impl Layout {
+    fn new(batch: &BatchParameters) -> Result<Layout> {
+        if batch.batch_size == 0 {
+            return Err(Error::InvalidParameters(
+                "batch_size must be positive".to_string(),
+            ));
+        }
+
+        let mut buckets = vec![Vec::new(); batch.num_buckets() as usize];
+        for index in 0..batch.ele_num {
+            for bucket in batch.candidate_buckets(index) {
+                buckets[bucket as usize].push(index);
+            }
+        }
+
+        // every bucket is padded to the largest one
+        let bucket_size = buckets.iter().map(Vec::len).max().unwrap_or(0).max(1) as u32;
+        let params = PirParameters::new(
+            bucket_size,
+            batch.ele_size,
+            batch.poly_degree,
+            batch.log_plain_mod,
+            batch.d,
+        )?;
+
+        Ok(Layout { buckets, params })
+    }
+
+    // position of element `index` within `bucket`
+    fn position(&self, bucket: u32, index: u32) -> Option<u32> {
+        self.buckets[bucket as usize]
+            .binary_search(&index)
+            .ok()
+            .map(|p| p as u32)
+    }
+}
+
+/// Serves a batch PIR database, one `PirServer` per bucket. Galois keys are only
+/// registered with the first bucket, whose copy every bucket replies with, so a client
+/// costs the memory of one key rather than one per bucket.
+pub struct BatchPirServer {
+    batch: BatchParameters,
+    layout: Layout,
+    servers: Vec<PirServer>,
+}
+
+impl BatchPirServer {
+    pub fn new(batch: &BatchParameters) -> Result<BatchPirServer> {
+        let layout = Layout::new(batch)?;
+        let servers = (0..layout.buckets.len())
+            .map(|_| PirServer::new(&layout.params))
+            .collect::<Result<Vec<PirServer>>>()?;
+
+        Ok(BatchPirServer {
+            batch: batch.clone(),
+            layout,
+            servers,
+        })
+    }
+
+    pub fn params(&self) -> &BatchParameters {
+        &self.batch
+    }
+
+    /// PIR parameters of each bucket.
+    pub fn bucket_params(&self) -> &PirParameters {
+        &self.layout.params
+    }
+
+    pub fn setup<T: Pod>(&mut self, collection: &[T]) -> Result<()> {
+        // checked before casting, which panics on zero-sized types
+        let ele_size = self.batch.ele_size as usize;
+        if mem::size_of::<T>() != ele_size {
+            return Err(Error::SizeMismatch {
+                expected: ele_size,
+                actual: mem::size_of::<T>(),
+            });
+        }
+        self.setup_bytes(bytemuck::cast_slice(collection), ele_size)
+    }
+
+    pub fn setup_bytes(&mut self, collection: &[u8], element_size: usize) -> Result<()> {
+        let ele_size = self.batch.ele_size as usize;
+        if element_size != ele_size {
+            return Err(Error::SizeMismatch {
+                expected: ele_size,
+                actual: element_size,
+            });
+        }
+
+        let expected = self.batch.ele_num as usize * ele_size;
+        if collection.len() != expected {
+            return Err(Error::SizeMismatch {
+                expected,
+                actual: collection.len(),
+            });
+        }
+
+        let bucket_size = self.layout.params.ele_num() as usize;
+        for (server, bucket) in self.servers.iter_mut().zip(self.layout.buckets.iter()) {
+            let mut db = vec![0u8; bucket_size * ele_size];
+            for (pos, &index) in bucket.iter().enumerate() {
+                let src = index as usize * ele_size;
+                db[pos * ele_size..(pos + 1) * ele_size]
+                    .copy_from_slice(&collection[src..src + ele_size]);
+            }
+            server.setup_bytes(&db, ele_size)?;
+        }
+
+        Ok(())
+    }
+
+    /// Registers the key of a `BatchPirClient`, once for all buckets.
+    pub fn set_galois_key(&mut self, key: &[u8]) -> Result<ClientHandle> {
+        self.servers[0].set_galois_key(key)
+    }
+
+    /// Answers one query per bucket, in bucket order.
+    pub fn gen_replies(&self, queries: &[PirQuery], client: ClientHandle) -> Result<Vec<PirReply>> {
+        if queries.len() != self.servers.len() {
+            return Err(Error::SizeMismatch {
+                expected: self.servers.len(),
+                actual: queries.len(),
+            });
+        }
+
+        self.servers
+            .iter()
+            .zip(queries.iter())
+            .map(|(server, query)| server.gen_reply_with_keys(query, client, &self.servers[0]))
+            .collect()
+    }
+}
+
+/// Client-side record of which bucket serves which requested element. It must be kept
+/// secret: it reveals the requested indices.
+pub struct BatchAssignment {
+    indices: Vec<u32>,
+    // requested index and position in the bucket, per bucket
+    buckets: Vec<Option<(u32, u32)>>,
+}
+
+impl BatchAssignment {
+    /// The requested indices, in the order they were passed to `gen_queries`.
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+}
+
+pub struct BatchPirClient {
+    batch: BatchParameters,
+    layout: Layout,
+    client: PirClient,
+}
+
+impl BatchPirClient {
+    pub fn new(batch: &BatchParameters) -> Result<BatchPirClient> {
+        let layout = Layout::new(batch)?;
+        let client = PirClient::new(&layout.params)?;
+
+        Ok(BatchPirClient {
+            batch: batch.clone(),
+            layout,
+            client,
+        })
+    }
+
+    pub fn params(&self) -> &BatchParameters {
+        &self.batch
+    }
+
+    pub fn get_key(&self) -> &Vec<u8> {
+        self.client.get_key()
+    }
+
+    pub fn handle(&self) -> ClientHandle {
+        self.client.handle()
+    }
+
+    /// Generates one query per bucket retrieving `indices` (at most `batch_size` of them,
+    /// duplicates allowed). Buckets that serve none of them get a query for a random
+    /// position so that the server cannot tell them apart.
+    pub fn gen_queries(&self, indices: &[u32]) -> Result<(Vec<PirQuery>, BatchAssignment)> {
+        let mut distinct = indices.to_vec();
+        distinct.sort_unstable();
+        distinct.dedup();
+
+        if distinct.len() > self.batch.batch_size as usize {
+            return Err(Error::SizeMismatch {
+                expected: self.batch.batch_size as usize,
+                actual: distinct.len(),
+            });
+        }
+
+        if let Some(&index) = distinct.iter().find(|&&i| i >= self.batch.ele_num) {
+            return Err(Error::IndexOutOfRange {
+                index: index as usize,
+                len: self.batch.ele_num as usize,
+            });
+        }
+
+        let placement = self.cuckoo_insert(&distinct)?;
+
+        let mut rng = rand::thread_rng();
+        let bucket_size = self.layout.params.ele_num();
+        let mut buckets = Vec::with_capacity(placement.len());
+        let mut queries = Vec::with_capacity(placement.len());
+
+        for (bucket, slot) in placement.into_iter().enumerate() {
+            match slot {
+                Some(index) => {
+                    let pos = self.layout.position(bucket as u32, index).ok_or(
+                        Error::IndexOutOfRange {
+                            index: index as usize,
+                            len: self.batch.ele_num as usize,
+                        },
+                    )?;
+                    queries.push(self.client.gen_query(pos)?);
+                    buckets.push(Some((index, pos)));
+                }
+                None => {
+                    queries.push(self.client.gen_query(rng.gen_range(0..bucket_size))?);
+                    buckets.push(None);
+                }
+            }
+        }
+
+        let assignment = BatchAssignment {
+            indices: indices.to_vec(),
+            buckets,
+        };
+        Ok((queries, assignment))
+    }
+
+    /// Decodes the replies to a batch and returns the requested elements in the order
+    /// the indices were passed to `gen_queries`.
+    pub fn decode_replies(
+        &self,
+        assignment: &BatchAssignment,
+        replies: &[PirReply],
+    ) -> Result<Vec<Vec<u8>>> {
+        if replies.len() != assignment.buckets.len() {
+            return Err(Error::SizeMismatch {
+                expected: assignment.buckets.len(),
+                actual: replies.len(),
+            });
+        }
+
+        let mut found: Vec<(u32, Vec<u8>)> = Vec::new();
+        for (slot, reply) in assignment.buckets.iter().zip(replies.iter()) {
+            if let Some((index, pos)) = *slot {
+                found.push((index, self.client.decode_reply_to_vec(pos, reply)?));
+            }
+        }
+
+        assignment
+            .indices
+            .iter()
+            .map(|index| {
+                found
+                    .iter()
+                    .find(|(i, _)| i == index)
+                    .map(|(_, e)| e.clone())
+                    .ok_or(Error::IndexOutOfRange {
+                        index: *index as usize,
+                        len: self.batch.ele_num as usize,
+                    })
+            })
+            .collect()
+    }
+
+    // places every index in a distinct bucket among its candidates; returns the index
+    // held by each bucket
+    fn cuckoo_insert(&self, indices: &[u32]) -> Result<Vec<Option<u32>>> {
+        let mut rng = rand::thread_rng();
+        let mut table: Vec<Option<u32>> = vec![None; self.batch.num_buckets() as usize];
+
+        for &index in indices {
+            let mut current = index;
+            let mut placed = false;
+
+            for _ in 0..MAX_EVICTIONS {
+                let candidates = self.batch.candidate_buckets(current);
+                if let Some(&free) = candidates.iter().find(|&&b| table[b as usize].is_none()) {
+                    table[free as usize] = Some(current);
+                    placed = true;
+                    break;
+                }
+
+                // evict a random occupant and try to re-place it
+                let victim = candidates[rng.gen_range(0..candidates.len())] as usize;
+                current = table[victim].replace(current).unwrap_or(current);
+            }
+
+            if !placed {
+                return Err(Error::InsertionFailed(format!(
+                    "could not assign {} indices to {} buckets",
+                    indices.len(),
+                    table.len()
+                )));
+            }
+        }
+
+        Ok(table)
+    }
+}
@@ -1,27 +1,70 @@
-use libc;
+use bytemuck::Pod;
+use libc::{self, c_char};
 use std::mem;
+use std::ptr;
 use std::slice;
 
-use super::{PirQuery, PirReply};
+use super::params::PirParameters;
+use super::seeded::{self, ExpandSeedFn};
+use super::{check, check_input, ClientHandle, Error, PirQuery, PirReply, Result};
 
-extern "C" {
-    fn new_parameters(ele_num: u32, ele_size: u32, N: u32, logt: u32, d: u32) -> *mut libc::c_void;
-    fn delete_parameters(params: *mut libc::c_void);
+mod decoded;
+mod state;
+pub use decoded::DecodedReply;
 
-    fn new_pir_client(params: *const libc::c_void) -> *mut libc::c_void;
+extern "C" {
+    fn new_pir_client(
+        params: *const libc::c_void,
+        pir_client: &mut *mut libc::c_void,
+        error: &mut *mut c_char,
+    ) -> i32;
     fn delete_pir_client(pir_client: *mut libc::c_void);
 
-    fn get_fv_index(pir_client: *const libc::c_void, ele_idx: u32, ele_size: u32) -> u32;
-    fn get_fv_offset(pir_client: *const libc::c_void, ele_idx: u32, ele_size: u32) -> u32;
+    fn get_fv_index(
+        pir_client: *const libc::c_void,
+        ele_idx: u32,
+        ele_size: u32,
+        fv_index: &mut u32,
+        error: &mut *mut c_char,
+    ) -> i32;
+    fn get_fv_offset(
+        pir_client: *const libc::c_void,
+        ele_idx: u32,
+        ele_size: u32,
+        fv_offset: &mut u32,
+        error: &mut *mut c_char,
+    ) -> i32;
 
-    fn get_galois_key(pir_client: *const libc::c_void, key_size: &mut u32) -> *mut u8;
+    fn get_seeded_galois_key(
+        pir_client: *const libc::c_void,
+        params: *const libc::c_void,
+        seed: *const u8,
+        expand: ExpandSeedFn,
+        key: &mut *mut u8,
+        key_size: &mut u32,
+        error: &mut *mut c_char,
+    ) -> i32;
 
     fn generate_query(
         pir_client: *const libc::c_void,
         index: u32,
+        query: &mut *mut u8,
+        query_size: &mut u32,
+        query_num: &mut u32,
+        error: &mut *mut c_char,
+    ) -> i32;
+
+    fn generate_seeded_query(
+        pir_client: *const libc::c_void,
+        params: *const libc::c_void,
+        index: u32,
+        seed: *const u8,
+        expand: ExpandSeedFn,
+        query: &mut *mut u8,
         query_size: &mut u32,
         query_num: &mut u32,
-    ) -> *mut u8;
+        error: &mut *mut c_char,
+    ) -> i32;
 
     fn decode_reply(
         pir_client: *const libc::c_void,
@@ -29,135 +72,324 @@ extern "C" {
         reply: *const u8,
         reply_size: u32,
         reply_num: u32,
+        result: *mut u8,
         result_size: &mut u32,
-    ) -> *mut u8;
+        budgets: *mut u32,
+        error: &mut *mut c_char,
+    ) -> i32;
 }
 
 pub struct PirClient {
     client: *mut libc::c_void,
-    params: *mut libc::c_void,
+    params: PirParameters,
     ele_size: u32,
+    column_size: u32,
     ele_num: u32,
     key: Vec<u8>,
+    seeded_key: Vec<u8>,
 }
 
 impl Drop for PirClient {
     fn drop(&mut self) {
         unsafe {
             delete_pir_client(self.client);
-            delete_parameters(self.params);
         }
     }
 }
 
 impl PirClient {
-    pub fn new(
-        ele_num: u32,
-        ele_size: u32,
-        poly_degree: u32,
-        log_plain_mod: u32,
-        d: u32,
-    ) -> PirClient {
+    pub fn new(params: &PirParameters) -> Result<PirClient> {
+        let mut error: *mut c_char = ptr::null_mut();
 
-        let param_ptr: *mut libc::c_void =
-            unsafe { new_parameters(ele_num, ele_size, poly_degree, log_plain_mod, d) };
+        let mut client_ptr: *mut libc::c_void = ptr::null_mut();
+        unsafe {
+            let status = new_pir_client(params.as_ptr(), &mut client_ptr, &mut error);
+            check(status, error)?;
+        }
 
-        let client_ptr: *mut libc::c_void = unsafe { new_pir_client(param_ptr) };
+        let mut client = PirClient::wrap(client_ptr, params);
 
         let mut key_size: u32 = 0;
+        let seed = seeded::new_seed();
 
-        let key: Vec<u8> = unsafe {
-            let ptr = get_galois_key(client_ptr, &mut key_size);
-            let key = slice::from_raw_parts_mut(ptr as *mut u8, key_size as usize).to_vec();
+        // the full key is the expansion of the seeded one, so that both give the same handle
+        let seeded_key = unsafe {
+            let mut ptr: *mut u8 = ptr::null_mut();
+            let status = get_seeded_galois_key(
+                client.client,
+                params.as_ptr(),
+                seed.as_ptr(),
+                seeded::expand_seed,
+                &mut ptr,
+                &mut key_size,
+                &mut error,
+            );
+            check(status, error)?;
+            let key = slice::from_raw_parts_mut(ptr, key_size as usize).to_vec();
             libc::free(ptr as *mut libc::c_void);
             key
         };
+        client.set_key(seeded_key)?;
 
+        Ok(client)
+    }
+
+    // takes ownership of a C++ client; the Galois key is left empty
+    fn wrap(client: *mut libc::c_void, params: &PirParameters) -> PirClient {
         PirClient {
-            client: client_ptr,
-            params: param_ptr,
-            ele_size,
-            ele_num,
-            key,
+            client,
+            params: params.clone(),
+            ele_size: params.ele_size(),
+            column_size: params.column_size(),
+            ele_num: params.ele_num(),
+            key: Vec::new(),
+            seeded_key: Vec::new(),
         }
     }
 
+    fn set_key(&mut self, seeded_key: Vec<u8>) -> Result<()> {
+        self.key = seeded::expand_key(&self.params, &seeded_key)?;
+        self.seeded_key = seeded_key;
+        Ok(())
+    }
+
+    pub fn params(&self) -> &PirParameters {
+        &self.params
+    }
+
     pub fn get_key(&self) -> &Vec<u8> {
         &self.key
     }
 
-    pub fn gen_query(&self, index: u32) -> PirQuery {
-        assert!(index <= self.ele_num);
+    /// The Galois key in seeded form, about half the size of `get_key`. A server accepts
+    /// either form and gives both the same handle.
+    pub fn get_compressed_key(&self) -> &[u8] {
+        &self.seeded_key
+    }
+
+    /// The handle `PirServer::set_galois_key` returns for the key of this client.
+    pub fn handle(&self) -> ClientHandle {
+        ClientHandle::for_key(&self.key)
+    }
+
+    pub fn gen_query(&self, index: u32) -> Result<PirQuery> {
+        let fv_index = self.fv_index(index)?;
+        let mut error: *mut c_char = ptr::null_mut();
         let mut query_size: u32 = 0; // # of bytes
         let mut query_num: u32 = 0; // # of ciphertexts
 
         let query: Vec<u8> = unsafe {
-            let fv_index = get_fv_index(self.client, index, self.ele_size);
-            let ptr = generate_query(self.client, fv_index, &mut query_size, &mut query_num);
-            let q = slice::from_raw_parts_mut(ptr as *mut u8, query_size as usize).to_vec();
+            let mut ptr: *mut u8 = ptr::null_mut();
+            let status = generate_query(
+                self.client,
+                fv_index,
+                &mut ptr,
+                &mut query_size,
+                &mut query_num,
+                &mut error,
+            );
+            check(status, error)?;
+            let q = slice::from_raw_parts_mut(ptr, query_size as usize).to_vec();
+            libc::free(ptr as *mut libc::c_void);
+            q
+        };
+
+        Ok(PirQuery {
+            query,
+            num: query_num,
+        })
+    }
+
+    /// Like `gen_query`, but the query is in seeded form, about half the size. Servers
+    /// accept it in place of a full query.
+    pub fn gen_compressed_query(&self, index: u32) -> Result<PirQuery> {
+        let fv_index = self.fv_index(index)?;
+        let seed = seeded::new_seed();
+        let mut error: *mut c_char = ptr::null_mut();
+        let mut query_size: u32 = 0; // # of bytes
+        let mut query_num: u32 = 0; // # of ciphertexts per dimension
+
+        let query: Vec<u8> = unsafe {
+            let mut ptr: *mut u8 = ptr::null_mut();
+            let status = generate_seeded_query(
+                self.client,
+                self.params.as_ptr(),
+                fv_index,
+                seed.as_ptr(),
+                seeded::expand_seed,
+                &mut ptr,
+                &mut query_size,
+                &mut query_num,
+                &mut error,
+            );
+            check(status, error)?;
+            let q = slice::from_raw_parts_mut(ptr, query_size as usize).to_vec();
             libc::free(ptr as *mut libc::c_void);
             q
         };
 
-        PirQuery {
+        Ok(PirQuery {
             query,
             num: query_num,
+        })
+    }
+
+    // index of the FV plaintext holding the element at index
+    fn fv_index(&self, index: u32) -> Result<u32> {
+        self.check_index(index)?;
+        let mut error: *mut c_char = ptr::null_mut();
+        let mut fv_index: u32 = 0;
+        unsafe {
+            let status = get_fv_index(
+                self.client,
+                index,
+                self.column_size,
+                &mut fv_index,
+                &mut error,
+            );
+            check(status, error)?;
         }
+        Ok(fv_index)
     }
 
-    pub fn decode_reply<T>(&self, ele_index: u32, reply: &PirReply) -> T
-    where
-        T: Clone,
-    {
-        assert_eq!(self.ele_size as usize, mem::size_of::<T>());
-        let mut result_size: u32 = 0;
-        let result: T = unsafe {
-            // returns the content of the FV plaintext
-            let ptr = decode_reply(
+    /// Decodes the element at `ele_index` as a `T`, which must be exactly `ele_size`
+    /// bytes. `Pod` types are valid for any bytes, so a reply cannot produce an invalid
+    /// value.
+    pub fn decode_reply<T: Pod>(&self, ele_index: u32, reply: &PirReply) -> Result<T> {
+        if self.ele_size as usize != mem::size_of::<T>() {
+            return Err(Error::SizeMismatch {
+                expected: self.ele_size as usize,
+                actual: mem::size_of::<T>(),
+            });
+        }
+
+        let bytes = self.decode_reply_to_vec(ele_index, reply)?;
+        Ok(bytemuck::pod_read_unaligned(&bytes))
+    }
+
+    pub fn decode_reply_to_vec(&self, ele_index: u32, reply: &PirReply) -> Result<Vec<u8>> {
+        let decoded = self.decrypt_reply(ele_index, reply)?;
+        decoded.element(ele_index).ok_or(Error::IndexOutOfRange {
+            index: ele_index as usize,
+            len: self.ele_num as usize,
+        })
+    }
+
+    /// Decodes every element packed into the FV plaintext that holds `ele_index`, the
+    /// index the reply was queried for, with their indices. The neighbors of an element
+    /// come at no extra cost: the whole plaintext is decrypted anyway. As with
+    /// `decrypt_reply`, the index is needed to know which elements the reply holds.
+    pub fn decode_reply_all(
+        &self,
+        ele_index: u32,
+        reply: &PirReply,
+    ) -> Result<Vec<(u32, Vec<u8>)>> {
+        Ok(self.decrypt_reply(ele_index, reply)?.elements().collect())
+    }
+
+    // position of the element at index within its FV plaintext
+    fn fv_offset(&self, index: u32) -> Result<u32> {
+        let mut error: *mut c_char = ptr::null_mut();
+        let mut offset: u32 = 0;
+        unsafe {
+            let status = get_fv_offset(
                 self.client,
-                self.params,
-                reply.reply.as_ptr(),
-                reply.reply.len() as u32,
-                reply.num,
-                &mut result_size,
+                index,
+                self.column_size,
+                &mut offset,
+                &mut error,
             );
+            check(status, error)?;
+        }
+        Ok(offset)
+    }
 
-            // offset into the FV plaintext
-            let offset = get_fv_offset(self.client, ele_index, self.ele_size);
-            assert!(offset * self.ele_size + self.ele_size <= result_size as u32);
+    /// Remaining invariant noise budget, in bits, of every ciphertext of `reply`: the
+    /// smaller of its own and that of the ciphertexts its decryption is composed into. A
+    /// budget of 0 means the reply decrypts to garbage. Meant for tuning parameters, as it
+    /// costs a full decryption.
+    pub fn noise_budgets(&self, reply: &PirReply) -> Result<Vec<u32>> {
+        Ok(self.decrypt_columns(reply)?.2)
+    }
 
-            let r = slice::from_raw_parts_mut((ptr as *mut T).offset(offset as isize), 1).to_vec();
-            libc::free(ptr as *mut libc::c_void);
-            r[0].clone()
-        };
-        result
+    // the FV plaintexts of every column a reply carries, one after the other, the size of
+    // each and the noise budgets of the reply ciphertexts
+    fn decrypt_columns(&self, reply: &PirReply) -> Result<(Vec<u8>, usize, Vec<u32>)> {
+        if reply.num == 0 || reply.reply.is_empty() {
+            return Err(Error::MalformedCiphertext("empty reply".to_string()));
+        }
+
+        if !reply.reply.len().is_multiple_of(reply.num as usize) {
+            return Err(Error::MalformedCiphertext(format!(
+                "{} bytes cannot hold {} ciphertexts",
+                reply.reply.len(),
+                reply.num
+            )));
+        }
+
+        // the server concatenates the replies of all columns
+        let columns = self.params.num_columns();
+        if !reply.num.is_multiple_of(columns) {
+            return Err(Error::MalformedCiphertext(format!(
+                "{} ciphertexts cannot be split into {} columns",
+                reply.num, columns
+            )));
+        }
+
+        // every column is decoded straight into its place, with room for a full plaintext
+        let num = reply.num / columns;
+        let column_bytes = reply.reply.len() / columns as usize;
+        let mut bytes = vec![0u8; columns as usize * self.params.plaintext_size()];
+        let mut plaintext_size = self.params.plaintext_size();
+        let mut budgets = vec![0u32; reply.num as usize];
+        for (column, (ciphertexts, column_budgets)) in reply
+            .reply
+            .chunks_exact(column_bytes)
+            .zip(budgets.chunks_exact_mut(num as usize))
+            .enumerate()
+        {
+            let out = &mut bytes[column * plaintext_size..];
+            plaintext_size = self.decrypt(ciphertexts, num, out, column_budgets)?;
+        }
+        bytes.truncate(columns as usize * plaintext_size);
+        Ok((bytes, plaintext_size, budgets))
     }
 
-    pub fn decode_reply_to_vec(&self, ele_index: u32, reply: &PirReply) -> Vec<u8> {
-        let mut result_size: u32 = 0;
-        let result = unsafe {
-            // returns the content of the FV plaintext
-            let ptr = decode_reply(
+    // writes the content of the FV plaintext carried by `num` reply ciphertexts to `out`
+    // and their noise budgets to `budgets`, and returns the size of the content
+    fn decrypt(
+        &self,
+        reply: &[u8],
+        num: u32,
+        out: &mut [u8],
+        budgets: &mut [u32],
+    ) -> Result<usize> {
+        let mut error: *mut c_char = ptr::null_mut();
+        let mut result_size = out.len() as u32;
+        unsafe {
+            let status = decode_reply(
                 self.client,
-                self.params,
-                reply.reply.as_ptr(),
-                reply.reply.len() as u32,
-                reply.num,
+                self.params.as_ptr(),
+                reply.as_ptr(),
+                reply.len() as u32,
+                num,
+                out.as_mut_ptr(),
                 &mut result_size,
+                budgets.as_mut_ptr(),
+                &mut error,
             );
+            check_input(status, error)?;
+        }
+        Ok(result_size as usize)
+    }
 
-            // offset into the FV plaintext
-            let offset = get_fv_offset(self.client, ele_index, self.ele_size);
-            assert!(offset * self.ele_size + self.ele_size <= result_size as u32);
-
-            let r = slice::from_raw_parts_mut(
-                (ptr as *mut u8).offset((offset * self.ele_size) as isize),
-                self.ele_size as usize,
-            )
-            .to_vec();
-            libc::free(ptr as *mut libc::c_void);
-            r
-        };
-        result
+    fn check_index(&self, index: u32) -> Result<()> {
+        if index >= self.ele_num {
+            return Err(Error::IndexOutOfRange {
+                index: index as usize,
+                len: self.ele_num as usize,
+            });
+        }
+        Ok(())
     }
 }
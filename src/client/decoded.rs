@@ -0,0 +1,111 @@
+// Decrypting a reply once and taking any number of elements from it. The FV plaintexts of
+// all columns are kept in one buffer, one after the other; an element is split across the
+// columns, each holding `column_size` bytes of it at the same offset.
+
+use std::ops::Range;
+
+use super::PirClient;
+use crate::{Error, PirReply, Result};
+
+/// The decrypted FV plaintexts of a reply, from which the elements they pack can be taken
+/// without decrypting again.
+pub struct DecodedReply {
+    bytes: Vec<u8>,
+    plaintext_size: usize,
+    indices: Range<u32>,
+    column_size: usize,
+    ele_size: usize,
+    noise_budgets: Vec<u32>,
+}
+
+impl DecodedReply {
+    /// Indices of the elements packed into the reply's plaintexts.
+    pub fn indices(&self) -> Range<u32> {
+        self.indices.clone()
+    }
+
+    /// The decrypted plaintexts of every column, one after the other.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The element at `index` as one slice per column, without copying; the last slice
+    /// stops at the end of the element. `None` if the reply does not hold `index`.
+    pub fn element_chunks(&self, index: u32) -> Option<Vec<&[u8]>> {
+        if !self.indices.contains(&index) {
+            return None;
+        }
+        Some(self.chunks(index - self.indices.start))
+    }
+
+    /// The element at `index`, or `None` if the reply does not hold it.
+    pub fn element(&self, index: u32) -> Option<Vec<u8>> {
+        self.element_chunks(index).map(|chunks| chunks.concat())
+    }
+
+    /// Every element the reply holds, with its index.
+    pub fn elements(&self) -> impl Iterator<Item = (u32, Vec<u8>)> + '_ {
+        self.indices
+            .clone()
+            .map(move |index| (index, self.chunks(index - self.indices.start).concat()))
+    }
+
+    /// Remaining invariant noise budget, in bits, of every ciphertext of the reply; see
+    /// `PirClient::noise_budgets`.
+    pub fn noise_budgets(&self) -> &[u32] {
+        &self.noise_budgets
+    }
+
+    fn chunks(&self, offset: u32) -> Vec<&[u8]> {
+        let start = offset as usize * self.column_size;
+        self.bytes
+            .chunks_exact(self.plaintext_size)
+            .enumerate()
+            .map(|(column, plaintext)| {
+                let len = self
+                    .column_size
+                    .min(self.ele_size - column * self.column_size);
+                &plaintext[start..start + len]
+            })
+            .collect()
+    }
+}
+
+impl PirClient {
+    /// Decrypts a reply to the query for `ele_index`. Decrypting is the costly part of
+    /// decoding, so a reply whose elements are all wanted should be decrypted only once.
+    /// Fails with `NoiseBudgetExhausted` rather than return garbage if the noise of the
+    /// reply outgrew the parameters.
+    ///
+    /// The reply alone is not enough: it does not reveal which plaintext it holds, just
+    /// as the query hides it from the server. Without the queried index, the decrypted
+    /// bytes could not be matched to element indices, nor the padding after the last
+    /// element of the database told apart from elements.
+    pub fn decrypt_reply(&self, ele_index: u32, reply: &PirReply) -> Result<DecodedReply> {
+        self.check_index(ele_index)?;
+        let (bytes, plaintext_size, noise_budgets) = self.decrypt_columns(reply)?;
+        if let Some(ciphertext) = noise_budgets.iter().position(|&budget| budget == 0) {
+            return Err(Error::NoiseBudgetExhausted { ciphertext });
+        }
+
+        // elements are packed into plaintexts in index order
+        let first = ele_index - self.fv_offset(ele_index)?;
+        let end = (first + self.params.elements_per_plaintext()).min(self.ele_num);
+        let needed = (end - first) as usize * self.column_size as usize;
+        if needed > plaintext_size {
+            return Err(Error::SizeMismatch {
+                expected: needed,
+                actual: plaintext_size,
+            });
+        }
+
+        Ok(DecodedReply {
+            bytes,
+            plaintext_size,
+            indices: first..end,
+            column_size: self.column_size as usize,
+            ele_size: self.ele_size as usize,
+            noise_budgets,
+        })
+    }
+}
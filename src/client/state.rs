@@ -0,0 +1,175 @@
+// Exporting the secret state of a client, so that a restarted client keeps its keys: the
+// Galois key registered at servers stays valid and so does the client's handle.
+//
+// Layout, all integers little-endian:
+//   magic (8 bytes), format version (u32)
+//   poly_degree, log_plain_mod (u32 each)
+//   secret key length (u64), secret key
+//   Galois key length (u64), Galois key in seeded form
+//   checksum (u64) of everything above
+// The checksum detects corruption, not tampering.
+
+use libc::{self, c_char};
+use std::ptr;
+use zeroize::Zeroizing;
+
+use super::PirClient;
+use crate::hash::hash64;
+use crate::params::PirParameters;
+use crate::{check, check_input, Error, Result};
+
+extern "C" {
+    fn get_secret_key(
+        pir_client: *const libc::c_void,
+        key: *mut u8,
+        key_size: &mut u32,
+        error: &mut *mut c_char,
+    ) -> i32;
+
+    fn new_pir_client_with_secret_key(
+        params: *const libc::c_void,
+        key: *const u8,
+        key_size: u32,
+        pir_client: &mut *mut libc::c_void,
+        error: &mut *mut c_char,
+    ) -> i32;
+}
+
+const MAGIC: [u8; 8] = *b"SEALPIRS";
+
+// bumped whenever the layout above changes
+const FORMAT_VERSION: u32 = 1;
+
+const CHECKSUM_SEED: u64 = 0;
+
+// bytes before the secret key
+const HEADER_SIZE: usize = 8 + 4 + 2 * 4 + 8;
+
+const CHECKSUM_SIZE: usize = 8;
+
+impl PirClient {
+    /// Serializes the secret key and the Galois key of this client, to be restored with
+    /// `from_secret_state`.
+    ///
+    /// **The state contains the secret key.** Anyone holding it can decrypt every query
+    /// and reply of this client, i.e., learn which elements it retrieved. Keep it like a
+    /// private key: encrypted at rest and never sent to a server. The returned buffer is
+    /// wiped when dropped; copies made from it are not.
+    pub fn export_secret_state(&self) -> Result<Zeroizing<Vec<u8>>> {
+        let mut error: *mut c_char = ptr::null_mut();
+        let mut secret_size: u32 = 0;
+        unsafe {
+            let status = get_secret_key(self.client, ptr::null_mut(), &mut secret_size, &mut error);
+            check(status, error)?;
+        }
+
+        // allocated once and the secret key serialized straight into it, so that no other
+        // copy of the secret key is left behind
+        let secret_len = secret_size as usize;
+        let size = HEADER_SIZE + secret_len + 8 + self.seeded_key.len() + CHECKSUM_SIZE;
+        let mut state = Zeroizing::new(Vec::with_capacity(size));
+        state.extend_from_slice(&MAGIC);
+        state.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        state.extend_from_slice(&self.params.poly_degree().to_le_bytes());
+        state.extend_from_slice(&self.params.log_plain_mod().to_le_bytes());
+        state.extend_from_slice(&(secret_len as u64).to_le_bytes());
+        state.resize(HEADER_SIZE + secret_len, 0);
+        unsafe {
+            let status = get_secret_key(
+                self.client,
+                state[HEADER_SIZE..].as_mut_ptr(),
+                &mut secret_size,
+                &mut error,
+            );
+            check(status, error)?;
+        }
+        if secret_size as usize != secret_len {
+            return Err(Error::Seal("secret key size changed".to_string()));
+        }
+
+        state.extend_from_slice(&(self.seeded_key.len() as u64).to_le_bytes());
+        state.extend_from_slice(&self.seeded_key);
+        let checksum = hash64(CHECKSUM_SEED, &state);
+        state.extend_from_slice(&checksum.to_le_bytes());
+        Ok(state)
+    }
+
+    /// Restores a client from a state written by `export_secret_state`. The client keeps
+    /// its Galois key and handle, so servers that registered the key need nothing new.
+    ///
+    /// `params` only has to agree with the exported client on `poly_degree` and
+    /// `log_plain_mod`: a state stays usable after the database is resized.
+    pub fn from_secret_state(params: &PirParameters, state: &[u8]) -> Result<PirClient> {
+        let (secret, key) = parse_state(params, state)?;
+
+        let mut error: *mut c_char = ptr::null_mut();
+        let mut client_ptr: *mut libc::c_void = ptr::null_mut();
+        unsafe {
+            let status = new_pir_client_with_secret_key(
+                params.as_ptr(),
+                secret.as_ptr(),
+                secret.len() as u32,
+                &mut client_ptr,
+                &mut error,
+            );
+            check_input(status, error)?;
+        }
+
+        let mut client = PirClient::wrap(client_ptr, params);
+        client.set_key(key.to_vec())?;
+        Ok(client)
+    }
+}
+
+// validates a state and returns the secret key and the Galois key it holds
+fn parse_state<'a>(params: &PirParameters, state: &'a [u8]) -> Result<(&'a [u8], &'a [u8])> {
+    let invalid = |msg: &str| Err(Error::InvalidFile(msg.to_string()));
+
+    if state.len() < HEADER_SIZE + 8 + CHECKSUM_SIZE {
+        return invalid("state is truncated");
+    }
+    let (data, checksum) = state.split_at(state.len() - CHECKSUM_SIZE);
+    if hash64(CHECKSUM_SEED, data).to_le_bytes() != checksum {
+        return invalid("checksum mismatch");
+    }
+
+    let u32_at = |at: usize| {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&data[at..at + 4]);
+        u32::from_le_bytes(bytes)
+    };
+    let u64_at = |at: usize| {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&data[at..at + 8]);
+        u64::from_le_bytes(bytes)
+    };
+    if data[..8] != MAGIC {
+        return invalid("not a client state");
+    }
+    if u32_at(8) != FORMAT_VERSION {
+        return Err(Error::InvalidFile(format!(
+            "unsupported format version {}",
+            u32_at(8)
+        )));
+    }
+    if u32_at(12) != params.poly_degree() || u32_at(16) != params.log_plain_mod() {
+        return Err(Error::InvalidParameters(
+            "the state was exported with different parameters".to_string(),
+        ));
+    }
+
+    // both lengths are checked against what is left, so they cannot overflow
+    let rest = &data[HEADER_SIZE..];
+    let secret_len = u64_at(20);
+    if secret_len > (rest.len() - 8) as u64 {
+        return invalid("secret key length does not match the state size");
+    }
+    let (secret, rest) = rest.split_at(secret_len as usize);
+    let mut len = [0u8; 8];
+    len.copy_from_slice(&rest[..8]);
+    let key = &rest[8..];
+    if u64::from_le_bytes(len) != key.len() as u64 {
+        return invalid("Galois key length does not match the state size");
+    }
+    Ok((secret, key))
+}
@@ -0,0 +1,154 @@
+use libc::c_char;
+use std::error;
+use std::ffi::CStr;
+use std::fmt;
+use std::io;
+
+use crate::ClientHandle;
+
+/// Errors returned by `PirClient` and `PirServer`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A collection, element or buffer does not have the expected size.
+    SizeMismatch { expected: usize, actual: usize },
+    /// An element index is outside of the database.
+    IndexOutOfRange { index: usize, len: usize },
+    /// A serialized query, reply or Galois key could not be interpreted.
+    MalformedCiphertext(String),
+    /// No Galois key is registered, or still resident, for the given client.
+    UnknownClient(ClientHandle),
+    /// The Galois key of the client is in the key store but not in memory, e.g. after an
+    /// eviction or a restart; `PirServer::load_galois_key` or
+    /// `PirServer::load_all_galois_keys` makes it usable again.
+    KeyNotLoaded(ClientHandle),
+    /// A different Galois key is registered under the handle of the key being registered.
+    KeyConflict(ClientHandle),
+    /// The requested SealPIR parameters are not supported.
+    InvalidParameters(String),
+    /// Cuckoo hashing could not place every item.
+    InsertionFailed(String),
+    /// A reply was requested before the database was set up.
+    DatabaseNotSet,
+    /// The database is memory-mapped from a file and cannot be updated in place.
+    ReadOnlyDatabase,
+    /// Every slot of the database is in use; `PirServer::resize` makes room.
+    CapacityExceeded { capacity: usize },
+    /// The encoding of the record at `index` takes `size` bytes, more than its slot.
+    RecordTooLarge {
+        index: usize,
+        size: usize,
+        slot_size: usize,
+    },
+    /// A record could not be encoded or decoded.
+    Encoding(String),
+    /// Reply ciphertext `ciphertext` has no noise budget left, so it decrypts to garbage;
+    /// the parameters need a smaller `d` or `log_plain_mod`.
+    NoiseBudgetExhausted { ciphertext: usize },
+    /// SEAL or SealPIR reported a failure.
+    Seal(String),
+    /// Reading or writing a file failed.
+    Io(String),
+    /// Saved data (a preprocessed database, a Galois key or a client's secret state) is
+    /// not valid: wrong header, truncated data or checksum mismatch.
+    InvalidFile(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::SizeMismatch { expected, actual } => {
+                write!(f, "size mismatch: expected {}, got {}", expected, actual)
+            }
+            Error::IndexOutOfRange { index, len } => {
+                write!(f, "index {} out of range for {} elements", index, len)
+            }
+            Error::MalformedCiphertext(msg) => write!(f, "malformed ciphertext: {}", msg),
+            Error::UnknownClient(client) => {
+                write!(f, "no Galois key registered for client {}", client)
+            }
+            Error::KeyNotLoaded(client) => {
+                write!(
+                    f,
+                    "Galois key of client {} is stored but not loaded",
+                    client
+                )
+            }
+            Error::KeyConflict(client) => write!(
+                f,
+                "a different Galois key is registered for client {}",
+                client
+            ),
+            Error::InvalidParameters(msg) => write!(f, "invalid parameters: {}", msg),
+            Error::InsertionFailed(msg) => write!(f, "insertion failed: {}", msg),
+            Error::DatabaseNotSet => write!(f, "database has not been set up"),
+            Error::ReadOnlyDatabase => write!(f, "database is memory-mapped and read-only"),
+            Error::CapacityExceeded { capacity } => {
+                write!(f, "all {} slots of the database are in use", capacity)
+            }
+            Error::RecordTooLarge {
+                index,
+                size,
+                slot_size,
+            } => write!(
+                f,
+                "record {} takes {} bytes, but slots hold {}",
+                index, size, slot_size
+            ),
+            Error::Encoding(msg) => write!(f, "encoding error: {}", msg),
+            Error::NoiseBudgetExhausted { ciphertext } => write!(
+                f,
+                "reply ciphertext {} has no noise budget left",
+                ciphertext
+            ),
+            Error::Seal(msg) => write!(f, "SEAL error: {}", msg),
+            Error::Io(msg) => write!(f, "I/O error: {}", msg),
+            Error::InvalidFile(msg) => write!(f, "invalid file: {}", msg),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e.to_string())
+    }
+}
+
+// status codes returned by the C++ bindings (see pir_rust.hpp)
+const PIR_OK: i32 = 0;
+const PIR_INVALID_ARGUMENT: i32 = 1;
+const PIR_LOGIC_ERROR: i32 = 2;
+
+// takes ownership of an error message allocated by the bindings
+unsafe fn take_message(message: *mut c_char) -> String {
+    if message.is_null() {
+        return "unknown error".to_string();
+    }
+    let msg = CStr::from_ptr(message).to_string_lossy().into_owned();
+    libc::free(message as *mut libc::c_void);
+    msg
+}
+
+/// Converts the status code and error message returned by a binding into a `Result`.
+pub(crate) unsafe fn check(status: i32, message: *mut c_char) -> Result<()> {
+    match status {
+        PIR_OK => Ok(()),
+        _ => Err(Error::Seal(take_message(message))),
+    }
+}
+
+/// Like `check`, but for bindings that deserialize caller-provided bytes: SEAL rejects
+/// such bytes with `std::invalid_argument`/`std::logic_error`, which are reported as
+/// `MalformedCiphertext`.
+pub(crate) unsafe fn check_input(status: i32, message: *mut c_char) -> Result<()> {
+    match status {
+        PIR_OK => Ok(()),
+        PIR_INVALID_ARGUMENT | PIR_LOGIC_ERROR => {
+            Err(Error::MalformedCiphertext(take_message(message)))
+        }
+        _ => Err(Error::Seal(take_message(message))),
+    }
+}
@@ -0,0 +1,63 @@
+// Deterministic keyed hashing shared by the client and the server. std's hashers are not
+// guaranteed to be stable across Rust releases, so both sides would not necessarily agree
+// on where an item is placed. These hashes are not cryptographic.
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+// splitmix64 finalizer
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// FNV-1a over `data`, keyed by `seed` and finalized with splitmix64.
+pub(crate) fn hash64(seed: u64, data: &[u8]) -> u64 {
+    let mut hasher = Hasher64::new(seed);
+    hasher.write(data);
+    hasher.finish()
+}
+
+/// Incremental `hash64`: writing the data in several pieces gives the same hash as
+/// hashing it at once.
+pub(crate) struct Hasher64 {
+    h: u64,
+    len: u64,
+}
+
+impl Hasher64 {
+    pub(crate) fn new(seed: u64) -> Hasher64 {
+        Hasher64 {
+            h: FNV_OFFSET ^ mix(seed),
+            len: 0,
+        }
+    }
+
+    pub(crate) fn write(&mut self, data: &[u8]) {
+        for &b in data {
+            self.h ^= b as u64;
+            self.h = self.h.wrapping_mul(FNV_PRIME);
+        }
+        self.len += data.len() as u64;
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        mix(self.h ^ self.len)
+    }
+}
+
+/// Returns `count` distinct values in `0..range` derived from `seed` and `data`.
+/// `range` must be at least `count`.
+pub(crate) fn distinct_slots(seed: u64, data: &[u8], count: usize, range: u32) -> Vec<u32> {
+    let mut slots = Vec::with_capacity(count);
+    let mut counter: u64 = 0;
+    while slots.len() < count {
+        let slot = (hash64(seed.wrapping_add(counter), data) % range as u64) as u32;
+        if !slots.contains(&slot) {
+            slots.push(slot);
+        }
+        counter += 1;
+    }
+    slots
+}
@@ -0,0 +1,272 @@
+//! Keyword PIR: retrieve a value by an arbitrary byte-string key.
+//!
+//! The server cuckoo-hashes its entries into a table of `num_slots` slots, each holding
+//! a tag derived from the key followed by the value. A key can only live in one of its
+//! `NUM_KEY_HASHES` candidate slots, so the client always queries all of them and
+//! compares the tags it gets back; the server learns neither the key nor whether it
+//! was present.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use super::client::PirClient;
+use super::hash;
+use super::params::PirParameters;
+use super::server::PirServer;
+use super::{ClientHandle, Error, PirQuery, PirReply, Result};
+
+/// Number of candidate slots per key, and thus of queries per lookup.
+pub const NUM_KEY_HASHES: usize = 3;
+
+/// Bytes of key tag stored in front of each value.
+pub const TAG_SIZE: usize = 16;
+
+// fraction of slots filled; cuckoo hashing with three choices succeeds up to ~0.9
+const LOAD_FACTOR: f64 = 0.8;
+
+// seeds tried per table size before the table is grown
+const MAX_SEEDS: usize = 16;
+
+// evictions attempted before insertion with the current seed gives up
+const MAX_EVICTIONS: usize = 500;
+
+// separate the tag hashes from the slot hashes
+const TAG_SEEDS: [u64; 2] = [0x7461_6731_0000_0000, 0x7461_6732_0000_0000];
+
+/// Public parameters of a keyword PIR table, chosen by `KeywordPirServer::new`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct KeywordParameters {
+    num_slots: u32,
+    value_size: u32,
+    seed: u64,
+    poly_degree: u32,
+    log_plain_mod: u32,
+    d: u32,
+}
+
+impl KeywordParameters {
+    pub fn num_slots(&self) -> u32 {
+        self.num_slots
+    }
+
+    pub fn value_size(&self) -> u32 {
+        self.value_size
+    }
+
+    /// Bytes per table slot: the tag followed by the value.
+    pub fn slot_size(&self) -> u32 {
+        TAG_SIZE as u32 + self.value_size
+    }
+
+    fn pir_params(&self) -> Result<PirParameters> {
+        PirParameters::new(
+            self.num_slots,
+            self.slot_size(),
+            self.poly_degree,
+            self.log_plain_mod,
+            self.d,
+        )
+    }
+
+    fn slots(&self, key: &[u8]) -> Vec<u32> {
+        hash::distinct_slots(self.seed, key, NUM_KEY_HASHES, self.num_slots)
+    }
+
+    fn tag(&self, key: &[u8]) -> [u8; TAG_SIZE] {
+        let mut tag = [0u8; TAG_SIZE];
+        tag[..8].copy_from_slice(&hash::hash64(self.seed ^ TAG_SEEDS[0], key).to_le_bytes());
+        tag[8..].copy_from_slice(&hash::hash64(self.seed ^ TAG_SEEDS[1], key).to_le_bytes());
+        tag
+    }
+}
+
+pub struct KeywordPirServer {
+    params: KeywordParameters,
+    server: PirServer,
+}
+
+impl KeywordPirServer {
+    /// Builds the table for `entries`, whose values must all be `value_size` bytes, and
+    /// sets up the PIR database over it. `poly_degree`, `log_plain_mod` and `d`
+    /// configure that database.
+    pub fn new<K, V>(
+        entries: &[(K, V)],
+        value_size: u32,
+        poly_degree: u32,
+        log_plain_mod: u32,
+        d: u32,
+    ) -> Result<KeywordPirServer>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let mut keys = HashSet::new();
+        for (key, value) in entries {
+            if value.as_ref().len() != value_size as usize {
+                return Err(Error::SizeMismatch {
+                    expected: value_size as usize,
+                    actual: value.as_ref().len(),
+                });
+            }
+            if !keys.insert(key.as_ref()) {
+                return Err(Error::InsertionFailed("duplicate key".to_string()));
+            }
+        }
+
+        let min_slots = (entries.len() as f64 / LOAD_FACTOR).ceil() as u32;
+        let mut params = KeywordParameters {
+            num_slots: min_slots.max(NUM_KEY_HASHES as u32),
+            value_size,
+            seed: 0,
+            poly_degree,
+            log_plain_mod,
+            d,
+        };
+
+        let mut rng = rand::thread_rng();
+        let table = loop {
+            let mut found = None;
+            for _ in 0..MAX_SEEDS {
+                params.seed = rng.gen();
+                if let Some(table) = cuckoo_insert(&params, entries) {
+                    found = Some(table);
+                    break;
+                }
+            }
+            match found {
+                Some(table) => break table,
+                None => params.num_slots += params.num_slots / 8 + 1,
+            }
+        };
+
+        let slot_size = params.slot_size() as usize;
+        let mut db = vec![0u8; params.num_slots as usize * slot_size];
+        for (slot, entry) in table.iter().enumerate() {
+            if let Some(i) = *entry {
+                let (key, value) = &entries[i];
+                let dst = &mut db[slot * slot_size..(slot + 1) * slot_size];
+                dst[..TAG_SIZE].copy_from_slice(&params.tag(key.as_ref()));
+                dst[TAG_SIZE..].copy_from_slice(value.as_ref());
+            }
+        }
+
+        let mut server = PirServer::new(&params.pir_params()?)?;
+        server.setup_bytes(&db, slot_size)?;
+
+        Ok(KeywordPirServer { params, server })
+    }
+
+    pub fn params(&self) -> &KeywordParameters {
+        &self.params
+    }
+
+    pub fn set_galois_key(&mut self, key: &[u8]) -> Result<ClientHandle> {
+        self.server.set_galois_key(key)
+    }
+
+    /// Answers the `NUM_KEY_HASHES` queries of one lookup.
+    pub fn gen_replies(&self, queries: &[PirQuery], client: ClientHandle) -> Result<Vec<PirReply>> {
+        if queries.len() != NUM_KEY_HASHES {
+            return Err(Error::SizeMismatch {
+                expected: NUM_KEY_HASHES,
+                actual: queries.len(),
+            });
+        }
+
+        queries
+            .iter()
+            .map(|query| self.server.gen_reply(query, client))
+            .collect()
+    }
+}
+
+pub struct KeywordPirClient {
+    params: KeywordParameters,
+    client: PirClient,
+}
+
+impl KeywordPirClient {
+    pub fn new(params: &KeywordParameters) -> Result<KeywordPirClient> {
+        let client = PirClient::new(&params.pir_params()?)?;
+
+        Ok(KeywordPirClient {
+            params: params.clone(),
+            client,
+        })
+    }
+
+    pub fn params(&self) -> &KeywordParameters {
+        &self.params
+    }
+
+    pub fn get_key(&self) -> &Vec<u8> {
+        self.client.get_key()
+    }
+
+    pub fn handle(&self) -> ClientHandle {
+        self.client.handle()
+    }
+
+    /// Generates the queries for every slot `key` may occupy.
+    pub fn gen_queries(&self, key: &[u8]) -> Result<Vec<PirQuery>> {
+        self.params
+            .slots(key)
+            .into_iter()
+            .map(|slot| self.client.gen_query(slot))
+            .collect()
+    }
+
+    /// Returns the value stored under `key`, or `None` if the server has no such key.
+    pub fn decode_replies(&self, key: &[u8], replies: &[PirReply]) -> Result<Option<Vec<u8>>> {
+        if replies.len() != NUM_KEY_HASHES {
+            return Err(Error::SizeMismatch {
+                expected: NUM_KEY_HASHES,
+                actual: replies.len(),
+            });
+        }
+
+        let tag = self.params.tag(key);
+        for (slot, reply) in self.params.slots(key).into_iter().zip(replies.iter()) {
+            let entry = self.client.decode_reply_to_vec(slot, reply)?;
+            if entry[..TAG_SIZE] == tag[..] {
+                return Ok(Some(entry[TAG_SIZE..].to_vec()));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+// places every entry in one of its candidate slots; returns the entry held by each slot,
+// or None if the seed does not work out
+fn cuckoo_insert<K, V>(params: &KeywordParameters, entries: &[(K, V)]) -> Option<Vec<Option<usize>>>
+where
+    K: AsRef<[u8]>,
+{
+    let mut rng = rand::thread_rng();
+    let mut table: Vec<Option<usize>> = vec![None; params.num_slots as usize];
+
+    for i in 0..entries.len() {
+        let mut current = i;
+        let mut placed = false;
+
+        for _ in 0..MAX_EVICTIONS {
+            let slots = params.slots(entries[current].0.as_ref());
+            if let Some(&free) = slots.iter().find(|&&s| table[s as usize].is_none()) {
+                table[free as usize] = Some(current);
+                placed = true;
+                break;
+            }
+
+            let victim = slots[rng.gen_range(0..slots.len())] as usize;
+            current = table[victim].replace(current).unwrap_or(current);
+        }
+
+        if !placed {
+            return None;
+        }
+    }
+
+    Some(table)
+}
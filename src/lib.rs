@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct PirQuery {
@@ -12,5 +14,68 @@ pub struct PirReply {
     pub num: u32,
 }
 
+/// Identifies the Galois key a `PirServer` answers a client's queries with. It is derived
+/// from the key, so registering the same key again gives the same handle and a client can
+/// compute its handle with `PirClient::handle`. Handles identify keys; they do not
+/// authenticate clients.
+///
+/// A handle is the first 8 bytes of a SHA-256 hash of the key, so finding a key with the
+/// handle of another client takes about 2^64 hashes; `PirServer::set_galois_key` also
+/// rejects a key that differs from the one registered under its handle.
+///
+/// A key registered in compressed form gets the handle of its full form, `get_key`, which
+/// is what `for_key` has to be given.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClientHandle(u64);
+
+// distinguishes handles from other hashes of the same bytes
+const HANDLE_DOMAIN: &[u8] = b"sealpir client handle";
+
+impl ClientHandle {
+    pub fn for_key(key: &[u8]) -> ClientHandle {
+        ClientHandle::for_digest(&key_digest(key))
+    }
+
+    pub(crate) fn for_digest(digest: &KeyDigest) -> ClientHandle {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        ClientHandle(u64::from_le_bytes(bytes))
+    }
+}
+
+/// SHA-256 hash of a Galois key, which handles are taken from. Servers compare the whole
+/// hash to tell apart keys with the same handle.
+pub(crate) type KeyDigest = [u8; 32];
+
+pub(crate) fn key_digest(key: &[u8]) -> KeyDigest {
+    Sha256::new()
+        .chain_update(HANDLE_DOMAIN)
+        .chain_update(key)
+        .finalize()
+        .into()
+}
+
+impl fmt::Display for ClientHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Re-exported for the `Pod` bound of typed elements, so that callers derive it from the
+/// same version.
+pub use bytemuck;
+
+mod error;
+pub(crate) use error::{check, check_input};
+pub use error::{Error, Result};
+
+pub mod batch;
 pub mod client;
+mod hash;
+pub mod keyword;
+pub mod params;
+pub mod record;
+mod seeded;
 pub mod server;
+pub mod varlen;
+mod wire;
@@ -0,0 +1,449 @@
+use libc::{self, c_char};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::ptr;
+use std::sync::Arc;
+
+use super::hash::Hasher64;
+use super::{check, Error, Result};
+
+mod cost;
+mod recommend;
+pub use cost::CostModel;
+pub use recommend::{Candidate, Objective, Recommendation, Rejection};
+
+extern "C" {
+    fn new_parameters(
+        ele_num: u32,
+        ele_size: u32,
+        N: u32,
+        logt: u32,
+        d: u32,
+        params: &mut *mut libc::c_void,
+        error: &mut *mut c_char,
+    ) -> i32;
+    fn delete_parameters(params: *mut libc::c_void);
+
+    fn get_pir_params(
+        params: *const libc::c_void,
+        n: &mut u64,
+        expansion_ratio: &mut u32,
+        dbc: &mut u32,
+        nvec: *mut u64,
+        d: u32,
+        error: &mut *mut c_char,
+    ) -> i32;
+}
+
+/// Polynomial modulus degree the bindings are built for: serialized ciphertexts are
+/// parsed with a fixed size (`CIPHER_SIZE`) that corresponds to N = 2048.
+pub const POLY_DEGREE: u32 = 2048;
+
+/// Size in bytes of a serialized ciphertext (`CIPHER_SIZE` in the bindings).
+pub const CIPHERTEXT_SIZE: usize = 32841;
+
+/// Largest supported number of database dimensions. The number of reply ciphertexts
+/// grows exponentially in `d`.
+pub const MAX_DIMENSIONS: u32 = 4;
+
+// bits of the coefficient modulus SealPIR uses
+pub(crate) const LOG_COEFF_MOD: u32 = 60;
+
+// SEAL limits the plaintext modulus to 60 bits, and it has to be smaller than the
+// 60-bit coefficient modulus SealPIR uses.
+const MAX_LOG_PLAIN_MOD: u32 = 59;
+
+// distinguishes fingerprints from other hashes of the same values
+const FINGERPRINT_SEED: u64 = 0x7061_7261_6d73;
+
+// owns the C++ `Parameters` object
+struct ParamsHandle(*mut libc::c_void);
+
+// The C++ object is never modified after `new_parameters` returns: the bindings only read
+// it, so it can be shared between threads.
+unsafe impl Send for ParamsHandle {}
+unsafe impl Sync for ParamsHandle {}
+
+impl Drop for ParamsHandle {
+    fn drop(&mut self) {
+        unsafe {
+            delete_parameters(self.0);
+        }
+    }
+}
+
+/// The values `PirParameters` are generated from.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+struct ParamsSpec {
+    ele_num: u32,
+    ele_size: u32,
+    poly_degree: u32,
+    log_plain_mod: u32,
+    d: u32,
+    epoch: u32,
+}
+
+/// SealPIR parameters for a database of `ele_num` elements of `ele_size` bytes.
+///
+/// Elements larger than one FV plaintext are split into `num_columns` chunks of
+/// `column_size` bytes, and each column is served as its own database with the same
+/// layout, so one query retrieves every chunk of an element.
+///
+/// A `PirClient` and a `PirServer` built from the same `PirParameters` (or from copies
+/// obtained by serializing it) are guaranteed to agree on the database layout. `ele_num`
+/// is the capacity of the database; `PirServer::resize` changes it and moves the
+/// parameters to the next epoch. Cloning is
+/// cheap: clones share the underlying SEAL parameters, also across threads.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(try_from = "ParamsSpec", into = "ParamsSpec")]
+pub struct PirParameters {
+    spec: ParamsSpec,
+    num_plaintexts: u64,
+    expansion_ratio: u32,
+    dbc: u32,
+    nvec: Vec<u64>,
+    handle: Arc<ParamsHandle>,
+}
+
+impl PirParameters {
+    pub fn new(
+        ele_num: u32,
+        ele_size: u32,
+        poly_degree: u32,
+        log_plain_mod: u32,
+        d: u32,
+    ) -> Result<PirParameters> {
+        PirParameters::try_from(ParamsSpec {
+            ele_num,
+            ele_size,
+            poly_degree,
+            log_plain_mod,
+            d,
+            epoch: 0,
+        })
+    }
+
+    /// The same parameters for a database of `ele_num` elements, in the next epoch.
+    pub fn resized(&self, ele_num: u32) -> Result<PirParameters> {
+        PirParameters::try_from(ParamsSpec {
+            ele_num,
+            epoch: self.spec.epoch.wrapping_add(1),
+            ..self.spec
+        })
+    }
+
+    // the same parameters in `epoch`
+    pub(crate) fn with_epoch(&self, epoch: u32) -> Result<PirParameters> {
+        if epoch == self.spec.epoch {
+            return Ok(self.clone());
+        }
+        PirParameters::try_from(ParamsSpec { epoch, ..self.spec })
+    }
+
+    pub fn builder() -> PirParametersBuilder {
+        PirParametersBuilder::default()
+    }
+
+    pub fn ele_num(&self) -> u32 {
+        self.spec.ele_num
+    }
+
+    pub fn ele_size(&self) -> u32 {
+        self.spec.ele_size
+    }
+
+    pub fn poly_degree(&self) -> u32 {
+        self.spec.poly_degree
+    }
+
+    pub fn log_plain_mod(&self) -> u32 {
+        self.spec.log_plain_mod
+    }
+
+    pub fn d(&self) -> u32 {
+        self.spec.d
+    }
+
+    /// Incremented every time the database is resized. Parameters from different epochs
+    /// describe different layouts, so a client holding parameters whose epoch differs from
+    /// the server's has to be rebuilt from the server's parameters.
+    pub fn epoch(&self) -> u32 {
+        self.spec.epoch
+    }
+
+    /// Hash of the values these parameters are generated from, epoch included. Framed
+    /// queries and replies carry it, so that a peer with different parameters is detected
+    /// before any ciphertext is parsed. It is not cryptographic.
+    pub fn fingerprint(&self) -> u64 {
+        let spec = &self.spec;
+        let mut hasher = Hasher64::new(FINGERPRINT_SEED);
+        for value in [
+            spec.ele_num,
+            spec.ele_size,
+            spec.poly_degree,
+            spec.log_plain_mod,
+            spec.d,
+            spec.epoch,
+        ]
+        .iter()
+        {
+            hasher.write(&value.to_le_bytes());
+        }
+        hasher.finish()
+    }
+
+    /// Number of FV plaintexts each column of the database is packed into.
+    pub fn num_plaintexts(&self) -> u64 {
+        self.num_plaintexts
+    }
+
+    /// Number of elements (of `column_size` bytes) packed into each FV plaintext.
+    pub fn elements_per_plaintext(&self) -> u32 {
+        elements_per_plaintext(
+            self.spec.poly_degree,
+            self.spec.log_plain_mod,
+            self.spec.column_size(),
+        )
+    }
+
+    /// Number of databases each element is split across; 1 unless an element does not
+    /// fit in one FV plaintext.
+    pub fn num_columns(&self) -> u32 {
+        self.spec.num_columns()
+    }
+
+    /// Bytes of an element stored in each column. The last column is zero-padded.
+    pub fn column_size(&self) -> u32 {
+        self.spec.column_size()
+    }
+
+    /// Number of bytes a decoded FV plaintext holds.
+    pub fn plaintext_size(&self) -> usize {
+        (self.spec.poly_degree as usize * self.spec.log_plain_mod as usize) / 8
+    }
+
+    /// Size of each of the `d` dimensions the plaintexts are arranged in.
+    pub fn nvec(&self) -> &[u64] {
+        &self.nvec
+    }
+
+    /// Number of plaintexts a ciphertext is decomposed into between dimensions.
+    pub fn expansion_ratio(&self) -> u32 {
+        self.expansion_ratio
+    }
+
+    /// Bits per digit of the decomposition Galois keys are generated with.
+    pub fn galois_decomposition_bits(&self) -> u32 {
+        self.dbc
+    }
+
+    // whether a Galois key generated for `other` can answer queries made with these
+    // parameters: keys only depend on the encryption parameters and the decomposition,
+    // not on the shape of the database
+    pub(crate) fn shares_keys_with(&self, other: &PirParameters) -> bool {
+        self.spec.poly_degree == other.spec.poly_degree
+            && self.spec.log_plain_mod == other.spec.log_plain_mod
+            && self.dbc == other.dbc
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const libc::c_void {
+        self.handle.0
+    }
+}
+
+impl PartialEq for PirParameters {
+    fn eq(&self, other: &PirParameters) -> bool {
+        self.spec == other.spec
+    }
+}
+
+impl Eq for PirParameters {}
+
+impl std::fmt::Debug for PirParameters {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("PirParameters")
+            .field("ele_num", &self.spec.ele_num)
+            .field("ele_size", &self.spec.ele_size)
+            .field("poly_degree", &self.spec.poly_degree)
+            .field("log_plain_mod", &self.spec.log_plain_mod)
+            .field("d", &self.spec.d)
+            .field("epoch", &self.spec.epoch)
+            .field("num_columns", &self.spec.num_columns())
+            .field("nvec", &self.nvec)
+            .finish()
+    }
+}
+
+impl TryFrom<ParamsSpec> for PirParameters {
+    type Error = Error;
+
+    fn try_from(spec: ParamsSpec) -> Result<PirParameters> {
+        spec.validate()?;
+
+        let mut error: *mut c_char = ptr::null_mut();
+        let mut param_ptr: *mut libc::c_void = ptr::null_mut();
+        let handle = unsafe {
+            let status = new_parameters(
+                spec.ele_num,
+                spec.column_size(),
+                spec.poly_degree,
+                spec.log_plain_mod,
+                spec.d,
+                &mut param_ptr,
+                &mut error,
+            );
+            check(status, error)?;
+            ParamsHandle(param_ptr)
+        };
+
+        let mut num_plaintexts: u64 = 0;
+        let mut expansion_ratio: u32 = 0;
+        let mut dbc: u32 = 0;
+        let mut nvec = vec![0u64; spec.d as usize];
+        unsafe {
+            let status = get_pir_params(
+                handle.0,
+                &mut num_plaintexts,
+                &mut expansion_ratio,
+                &mut dbc,
+                nvec.as_mut_ptr(),
+                spec.d,
+                &mut error,
+            );
+            check(status, error)?;
+        }
+
+        Ok(PirParameters {
+            spec,
+            num_plaintexts,
+            expansion_ratio,
+            dbc,
+            nvec,
+            handle: Arc::new(handle),
+        })
+    }
+}
+
+impl From<PirParameters> for ParamsSpec {
+    fn from(params: PirParameters) -> ParamsSpec {
+        params.spec
+    }
+}
+
+impl ParamsSpec {
+    // rejects values SealPIR would abort or misbehave on
+    fn validate(&self) -> Result<()> {
+        let invalid = |msg: String| Err(Error::InvalidParameters(msg));
+
+        if self.ele_num == 0 {
+            return invalid("ele_num must be positive".to_string());
+        }
+        if self.ele_size == 0 {
+            return invalid("ele_size must be positive".to_string());
+        }
+        if self.poly_degree != POLY_DEGREE {
+            return invalid(format!("poly_degree must be {}", POLY_DEGREE));
+        }
+        if self.log_plain_mod < 2 || self.log_plain_mod > MAX_LOG_PLAIN_MOD {
+            return invalid(format!(
+                "log_plain_mod must be between 2 and {}",
+                MAX_LOG_PLAIN_MOD
+            ));
+        }
+        if self.d == 0 || self.d > MAX_DIMENSIONS {
+            return invalid(format!("d must be between 1 and {}", MAX_DIMENSIONS));
+        }
+        Ok(())
+    }
+
+    // fewest columns whose chunks each fit in one plaintext
+    fn num_columns(&self) -> u32 {
+        let plaintext_size = self.poly_degree as u64 * self.log_plain_mod as u64 / 8;
+        (self.ele_size as u64).div_ceil(plaintext_size) as u32
+    }
+
+    fn column_size(&self) -> u32 {
+        self.ele_size.div_ceil(self.num_columns())
+    }
+}
+
+/// Builds `PirParameters`. `ele_num` and `ele_size` are required; the remaining values
+/// default to N = 2048, log t = 12 and d = 2.
+#[derive(Clone, Debug)]
+pub struct PirParametersBuilder {
+    ele_num: Option<u32>,
+    ele_size: Option<u32>,
+    poly_degree: u32,
+    log_plain_mod: u32,
+    d: u32,
+    epoch: u32,
+}
+
+impl Default for PirParametersBuilder {
+    fn default() -> PirParametersBuilder {
+        PirParametersBuilder {
+            ele_num: None,
+            ele_size: None,
+            poly_degree: POLY_DEGREE,
+            log_plain_mod: 12,
+            d: 2,
+            epoch: 0,
+        }
+    }
+}
+
+impl PirParametersBuilder {
+    pub fn ele_num(mut self, ele_num: u32) -> Self {
+        self.ele_num = Some(ele_num);
+        self
+    }
+
+    pub fn ele_size(mut self, ele_size: u32) -> Self {
+        self.ele_size = Some(ele_size);
+        self
+    }
+
+    pub fn poly_degree(mut self, poly_degree: u32) -> Self {
+        self.poly_degree = poly_degree;
+        self
+    }
+
+    pub fn log_plain_mod(mut self, log_plain_mod: u32) -> Self {
+        self.log_plain_mod = log_plain_mod;
+        self
+    }
+
+    pub fn d(mut self, d: u32) -> Self {
+        self.d = d;
+        self
+    }
+
+    pub fn epoch(mut self, epoch: u32) -> Self {
+        self.epoch = epoch;
+        self
+    }
+
+    pub fn build(&self) -> Result<PirParameters> {
+        let ele_num = self
+            .ele_num
+            .ok_or_else(|| Error::InvalidParameters("ele_num is not set".to_string()))?;
+        let ele_size = self
+            .ele_size
+            .ok_or_else(|| Error::InvalidParameters("ele_size is not set".to_string()))?;
+
+        PirParameters::try_from(ParamsSpec {
+            ele_num,
+            ele_size,
+            poly_degree: self.poly_degree,
+            log_plain_mod: self.log_plain_mod,
+            d: self.d,
+            epoch: self.epoch,
+        })
+    }
+}
+
+// mirrors SealPIR's coefficients_per_element and elements_per_ptxt
+fn elements_per_plaintext(poly_degree: u32, log_plain_mod: u32, ele_size: u32) -> u32 {
+    let coeffs_per_element = (8 * ele_size as u64).div_ceil(log_plain_mod as u64);
+    (poly_degree as u64 / coeffs_per_element) as u32
+}
@@ -0,0 +1,77 @@
+use super::{PirParameters, CIPHERTEXT_SIZE, LOG_COEFF_MOD};
+use crate::Result;
+
+/// Analytical estimate of the sizes and the server work of a SealPIR configuration.
+///
+/// Sizes are in bytes of the serialized form produced by the bindings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CostModel {
+    /// One Galois key per power of two up to N, each decomposed into
+    /// ceil(log q / dbc) ciphertexts. A server keeps one copy in memory for every
+    /// registered client, shared by its columns; a `BatchPirServer` shares it across its
+    /// buckets and a `VarLenPirServer` across its size classes.
+    pub galois_key_bytes: u64,
+    pub query_ciphertexts: u64,
+    pub query_bytes: u64,
+    /// Equal to `expansion_ratio^(d - 1)` per column: every dimension but the last turns
+    /// each ciphertext into `expansion_ratio` plaintexts.
+    pub reply_ciphertexts: u64,
+    pub reply_bytes: u64,
+    /// Memory held by the NTT-transformed database, padded to the product of the
+    /// dimension sizes in every column.
+    pub preprocessed_db_bytes: u64,
+    /// Plaintext-ciphertext multiplications per query.
+    pub server_multiplications: u64,
+}
+
+impl CostModel {
+    /// Estimates the costs of the parameters `PirParameters::new` would build from the
+    /// same arguments, without setting up a server.
+    pub fn new(
+        ele_num: u32,
+        ele_size: u32,
+        poly_degree: u32,
+        log_plain_mod: u32,
+        d: u32,
+    ) -> Result<CostModel> {
+        let params = PirParameters::new(ele_num, ele_size, poly_degree, log_plain_mod, d)?;
+        Ok(CostModel::from_params(&params))
+    }
+
+    pub fn from_params(params: &PirParameters) -> CostModel {
+        let n = params.poly_degree() as u64;
+        let ratio = params.expansion_ratio() as u64;
+        let nvec = params.nvec();
+        let columns = params.num_columns() as u64;
+        let ciphertext = CIPHERTEXT_SIZE as u64;
+
+        let galois_keys = n.trailing_zeros() as u64; // log2(N)
+        let key_ciphertexts =
+            (LOG_COEFF_MOD as u64).div_ceil(params.galois_decomposition_bits() as u64);
+
+        // the bindings serialize as many ciphertexts for every dimension as the first
+        // (largest) one needs
+        let query_ciphertexts = params.d() as u64 * nvec[0].div_ceil(n);
+        let reply_ciphertexts = columns * ratio.pow(params.d() - 1);
+
+        // dimension i multiplies every plaintext left after the previous dimensions, each
+        // of which expanded into `ratio` plaintexts
+        let mut server_multiplications = 0;
+        let mut remaining: u64 = nvec.iter().product();
+        let padded_plaintexts = columns * remaining;
+        for (i, &n_i) in nvec.iter().enumerate() {
+            server_multiplications += remaining * ratio.pow(i as u32);
+            remaining /= n_i;
+        }
+
+        CostModel {
+            galois_key_bytes: galois_keys * key_ciphertexts * ciphertext,
+            query_ciphertexts,
+            query_bytes: query_ciphertexts * ciphertext,
+            reply_ciphertexts,
+            reply_bytes: reply_ciphertexts * ciphertext,
+            preprocessed_db_bytes: padded_plaintexts * n * 8,
+            server_multiplications: columns * server_multiplications,
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use super::{CostModel, PirParameters, LOG_COEFF_MOD, MAX_DIMENSIONS, POLY_DEGREE};
+use crate::{Error, Result};
+
+// plaintext moduli tried by `recommend`
+const LOG_PLAIN_MODS: [u32; 9] = [8, 10, 12, 14, 16, 18, 20, 22, 24];
+
+/// What `PirParameters::recommend` minimizes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Objective {
+    /// Bytes uploaded per query.
+    QuerySize,
+    /// Bytes downloaded per reply.
+    ReplySize,
+    /// Plaintext-ciphertext multiplications the server performs per query.
+    ServerCompute,
+    /// Weighted sum of the three costs above, each first divided by the smallest value
+    /// any viable candidate achieves so that the weights are unitless.
+    Weighted {
+        query_size: f64,
+        reply_size: f64,
+        server_compute: f64,
+    },
+}
+
+/// Why `recommend` did not pick a candidate.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Rejection {
+    /// The configuration is not valid for this database.
+    Invalid(Error),
+    /// Replies would likely not decrypt: the estimated remaining noise budget (in bits)
+    /// is not positive.
+    InsufficientNoiseBudget(f64),
+    /// Viable, but another candidate scored better.
+    Outscored,
+}
+
+/// One configuration considered by `recommend`, with its estimated costs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candidate {
+    pub poly_degree: u32,
+    pub log_plain_mod: u32,
+    pub d: u32,
+    /// `None` if the configuration is invalid.
+    pub cost: Option<CostModel>,
+    /// Estimated noise budget (in bits) left in a reply; `None` if the configuration is
+    /// invalid.
+    pub noise_budget: Option<f64>,
+    /// Value of the objective; lower is better. `None` for non-viable candidates.
+    pub score: Option<f64>,
+    /// `None` for the chosen candidate.
+    pub rejection: Option<Rejection>,
+}
+
+/// Result of `PirParameters::recommend`.
+#[derive(Clone, Debug)]
+pub struct Recommendation {
+    pub params: PirParameters,
+    pub chosen: Candidate,
+    pub rejected: Vec<Candidate>,
+}
+
+impl PirParameters {
+    /// Enumerates the configurations supported by the bindings for a database of
+    /// `ele_num` elements of `ele_size` bytes and returns the one minimizing `objective`
+    /// among those whose replies are expected to decrypt correctly.
+    pub fn recommend(ele_num: u32, ele_size: u32, objective: Objective) -> Result<Recommendation> {
+        let mut viable: Vec<(PirParameters, Candidate)> = Vec::new();
+        let mut rejected: Vec<Candidate> = Vec::new();
+
+        for &log_plain_mod in LOG_PLAIN_MODS.iter() {
+            for d in 1..=MAX_DIMENSIONS {
+                match PirParameters::new(ele_num, ele_size, POLY_DEGREE, log_plain_mod, d) {
+                    Ok(params) => {
+                        let budget = params.expected_noise_budget();
+                        let mut candidate = Candidate {
+                            poly_degree: POLY_DEGREE,
+                            log_plain_mod,
+                            d,
+                            cost: Some(CostModel::from_params(&params)),
+                            noise_budget: Some(budget),
+                            score: None,
+                            rejection: None,
+                        };
+                        if budget > 0.0 {
+                            viable.push((params, candidate));
+                        } else {
+                            candidate.rejection = Some(Rejection::InsufficientNoiseBudget(budget));
+                            rejected.push(candidate);
+                        }
+                    }
+                    Err(e) => rejected.push(Candidate {
+                        poly_degree: POLY_DEGREE,
+                        log_plain_mod,
+                        d,
+                        cost: None,
+                        noise_budget: None,
+                        score: None,
+                        rejection: Some(Rejection::Invalid(e)),
+                    }),
+                }
+            }
+        }
+
+        if viable.is_empty() {
+            return Err(Error::InvalidParameters(format!(
+                "no supported parameters for {} elements of {} bytes",
+                ele_num, ele_size
+            )));
+        }
+
+        let costs: Vec<&CostModel> = viable.iter().filter_map(|(_, c)| c.cost.as_ref()).collect();
+        let min =
+            |f: fn(&CostModel) -> u64| costs.iter().map(|c| f(c)).min().unwrap_or(1).max(1) as f64;
+        let min_query = min(|c| c.query_bytes);
+        let min_reply = min(|c| c.reply_bytes);
+        let min_compute = min(|c| c.server_multiplications);
+
+        for (_, candidate) in viable.iter_mut() {
+            let cost = match &candidate.cost {
+                Some(cost) => cost,
+                None => continue,
+            };
+            let query = cost.query_bytes as f64;
+            let reply = cost.reply_bytes as f64;
+            let compute = cost.server_multiplications as f64;
+
+            candidate.score = Some(match objective {
+                Objective::QuerySize => query,
+                Objective::ReplySize => reply,
+                Objective::ServerCompute => compute,
+                Objective::Weighted {
+                    query_size,
+                    reply_size,
+                    server_compute,
+                } => {
+                    query_size * query / min_query
+                        + reply_size * reply / min_reply
+                        + server_compute * compute / min_compute
+                }
+            });
+        }
+
+        // ties go to the candidate with the larger noise budget
+        let best = (0..viable.len())
+            .min_by(|&a, &b| {
+                let (ca, cb) = (&viable[a].1, &viable[b].1);
+                ca.score
+                    .partial_cmp(&cb.score)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then(
+                        cb.noise_budget
+                            .partial_cmp(&ca.noise_budget)
+                            .unwrap_or(std::cmp::Ordering::Equal),
+                    )
+            })
+            .unwrap_or(0);
+
+        let (params, chosen) = viable.swap_remove(best);
+        for (_, mut candidate) in viable {
+            candidate.rejection = Some(Rejection::Outscored);
+            rejected.push(candidate);
+        }
+
+        Ok(Recommendation {
+            params,
+            chosen,
+            rejected,
+        })
+    }
+
+    /// Heuristic estimate of the invariant noise budget (in bits) left in a reply, the
+    /// estimate `recommend` ranks candidates by; `PirClient::noise_budgets` measures the
+    /// actual budget. Replies are unlikely to decrypt if it is not positive.
+    ///
+    /// A fresh ciphertext has about log2(q / t) - log2(N) bits. Every dimension starts
+    /// from a fresh query ciphertext, so only the most expensive dimension matters. Per
+    /// dimension: expanding a query ciphertext into m slots doubles the noise at each of
+    /// the log2(m) steps, multiplying by a plaintext with coefficients below t adds about
+    /// log2(t / 2 * sqrt(N)) bits, and summing nvec[i] products adds log2(nvec[i]) / 2.
+    pub fn expected_noise_budget(&self) -> f64 {
+        let log_n = (self.poly_degree() as f64).log2();
+        let log_t = self.log_plain_mod() as f64;
+        let fresh = LOG_COEFF_MOD as f64 - log_t - log_n;
+
+        let consumed = self
+            .nvec()
+            .iter()
+            .map(|&n_i| {
+                let slots = n_i.min(self.poly_degree() as u64).next_power_of_two() as f64;
+                slots.log2() + (log_t - 1.0) + log_n / 2.0 + (n_i as f64).log2() / 2.0
+            })
+            .fold(0.0, f64::max);
+
+        fresh - consumed
+    }
+}
@@ -0,0 +1,118 @@
+//! Databases of structured records.
+//!
+//! Each record is encoded with bincode's compact (varint) encoding into a slot of
+//! `ele_size` bytes and padded with zeros. The encoding is self-delimiting, so the padding
+//! is ignored when a slot is decoded and no length has to be stored. A record whose
+//! encoding does not fit in its slot is rejected rather than truncated.
+
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::PhantomData;
+
+use super::client::PirClient;
+use super::params::PirParameters;
+use super::server::PirServer;
+use super::{Error, PirReply, Result};
+
+// both sides have to agree on these options
+fn options() -> impl Options {
+    bincode::DefaultOptions::new().allow_trailing_bytes()
+}
+
+/// Records of type `T`, encoded into the slots of a database with parameters `params`.
+pub struct SerdePirDatabase<T> {
+    params: PirParameters,
+    bytes: Vec<u8>,
+    record: PhantomData<fn(&T)>,
+}
+
+impl<T: Serialize> SerdePirDatabase<T> {
+    /// Encodes one record per slot; there must be exactly `ele_num` records.
+    pub fn new(params: &PirParameters, records: &[T]) -> Result<SerdePirDatabase<T>> {
+        if records.len() != params.ele_num() as usize {
+            return Err(Error::SizeMismatch {
+                expected: params.ele_num() as usize,
+                actual: records.len(),
+            });
+        }
+
+        let slot_size = params.ele_size() as usize;
+        let mut bytes = vec![0u8; records.len() * slot_size];
+        for (index, (record, slot)) in records
+            .iter()
+            .zip(bytes.chunks_exact_mut(slot_size))
+            .enumerate()
+        {
+            encode_into(index, record, slot)?;
+        }
+
+        Ok(SerdePirDatabase {
+            params: params.clone(),
+            bytes,
+            record: PhantomData,
+        })
+    }
+
+    /// Encodes `record` as the element at `index` of a database with parameters `params`,
+    /// in the form `PirServer::update_elements` takes.
+    pub fn encode(params: &PirParameters, index: usize, record: &T) -> Result<(usize, Vec<u8>)> {
+        let mut slot = vec![0u8; params.ele_size() as usize];
+        encode_into(index, record, &mut slot)?;
+        Ok((index, slot))
+    }
+
+    pub fn params(&self) -> &PirParameters {
+        &self.params
+    }
+
+    /// The encoded slots, one after the other, as `PirServer::setup_bytes` takes them.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+fn encode_into<T: Serialize>(index: usize, record: &T, slot: &mut [u8]) -> Result<()> {
+    let size = options()
+        .serialized_size(record)
+        .map_err(|e| Error::Encoding(e.to_string()))? as usize;
+    if size > slot.len() {
+        return Err(Error::RecordTooLarge {
+            index,
+            size,
+            slot_size: slot.len(),
+        });
+    }
+    options()
+        .serialize_into(&mut slot[..size], record)
+        .map_err(|e| Error::Encoding(e.to_string()))
+}
+
+impl PirServer {
+    /// Sets up the database with the slots of `db`, which must have these parameters.
+    pub fn setup_records<T: Serialize>(&mut self, db: &SerdePirDatabase<T>) -> Result<()> {
+        if db.params() != self.params() {
+            return Err(Error::InvalidParameters(
+                "the records were encoded for different parameters".to_string(),
+            ));
+        }
+        self.setup_bytes(db.as_bytes(), self.params().ele_size() as usize)
+    }
+}
+
+impl PirClient {
+    /// Decodes the record at `ele_index` from a reply to a database set up with
+    /// `PirServer::setup_records`. A slot that holds no record, e.g., a free slot of a
+    /// database with spare capacity, fails with `Encoding` unless it happens to decode.
+    pub fn decode_record<T: DeserializeOwned>(
+        &self,
+        ele_index: u32,
+        reply: &PirReply,
+    ) -> Result<T> {
+        let slot = self.decode_reply_to_vec(ele_index, reply)?;
+        options()
+            .with_limit(slot.len() as u64)
+            .deserialize(&slot)
+            .map_err(|e| Error::Encoding(e.to_string()))
+    }
+}
@@ -0,0 +1,132 @@
+// Seeded encodings of Galois keys and queries: the uniformly random half of every ciphertext
+// is replaced by a seed, which the bindings expand again through `expand_seed`. The client
+// and the server must expand a seed identically, so it goes through ChaCha20, whose output is
+// fixed for a given seed and stream, and not through `rand`'s `StdRng`, which may change
+// between releases. The layout is documented in pir_rust.hpp.
+
+use libc::{self, c_char};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::ptr;
+use std::slice;
+
+use crate::params::PirParameters;
+use crate::{check_input, Error, PirQuery, Result};
+
+pub(crate) type ExpandSeedFn = unsafe extern "C" fn(
+    seed: *const u8,
+    stream: u64,
+    moduli: *const u64,
+    moduli_count: u32,
+    coeff_count: u64,
+    out: *mut u64,
+);
+
+extern "C" {
+    fn expand_seeded(
+        params: *const libc::c_void,
+        kind: u8,
+        data: *const u8,
+        size: u32,
+        expand: ExpandSeedFn,
+        expanded: &mut *mut u8,
+        expanded_size: &mut u32,
+        count: &mut u32,
+        error: &mut *mut c_char,
+    ) -> i32;
+}
+
+const MAGIC: [u8; 8] = *b"SEALPIRZ";
+
+pub(crate) const SEED_SIZE: usize = 32;
+
+const QUERY: u8 = 0;
+const GALOIS_KEY: u8 = 1;
+
+pub(crate) fn is_seeded(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+pub(crate) fn new_seed() -> [u8; SEED_SIZE] {
+    let mut seed = [0u8; SEED_SIZE];
+    rand::thread_rng().fill_bytes(&mut seed);
+    seed
+}
+
+// fills `out` with `coeff_count` coefficients per modulus, each uniform modulo its modulus
+pub(crate) unsafe extern "C" fn expand_seed(
+    seed: *const u8,
+    stream: u64,
+    moduli: *const u64,
+    moduli_count: u32,
+    coeff_count: u64,
+    out: *mut u64,
+) {
+    let mut key = [0u8; SEED_SIZE];
+    key.copy_from_slice(slice::from_raw_parts(seed, SEED_SIZE));
+    let mut rng = ChaCha20Rng::from_seed(key);
+    rng.set_stream(stream);
+
+    let moduli = slice::from_raw_parts(moduli, moduli_count as usize);
+    let out = slice::from_raw_parts_mut(out, moduli.len() * coeff_count as usize);
+    for (&modulus, coeffs) in moduli
+        .iter()
+        .zip(out.chunks_exact_mut(coeff_count as usize))
+    {
+        // rejection sampling below the largest multiple of the modulus avoids a bias
+        let limit = u64::MAX - u64::MAX % modulus;
+        for coeff in coeffs.iter_mut() {
+            *coeff = loop {
+                let value = rng.next_u64();
+                if value < limit {
+                    break value % modulus;
+                }
+            };
+        }
+    }
+}
+
+// the full serialization of a seeded encoding, and the number of ciphertexts in it
+fn expand(params: &PirParameters, kind: u8, data: &[u8]) -> Result<(Vec<u8>, u32)> {
+    let mut error: *mut c_char = ptr::null_mut();
+    let mut size: u32 = 0;
+    let mut count: u32 = 0;
+    unsafe {
+        let mut expanded: *mut u8 = ptr::null_mut();
+        let status = expand_seeded(
+            params.as_ptr(),
+            kind,
+            data.as_ptr(),
+            data.len() as u32,
+            expand_seed,
+            &mut expanded,
+            &mut size,
+            &mut count,
+            &mut error,
+        );
+        check_input(status, error)?;
+        let bytes = slice::from_raw_parts(expanded, size as usize).to_vec();
+        libc::free(expanded as *mut libc::c_void);
+        Ok((bytes, count))
+    }
+}
+
+pub(crate) fn expand_key(params: &PirParameters, key: &[u8]) -> Result<Vec<u8>> {
+    Ok(expand(params, GALOIS_KEY, key)?.0)
+}
+
+// `d` is the number of dimensions, each of which takes `query.num` ciphertexts
+pub(crate) fn expand_query(params: &PirParameters, query: &PirQuery, d: u32) -> Result<PirQuery> {
+    let (bytes, count) = expand(params, QUERY, &query.query)?;
+    if count as u64 != query.num as u64 * d as u64 {
+        return Err(Error::MalformedCiphertext(format!(
+            "seeded query holds {} ciphertexts instead of {}",
+            count,
+            query.num as u64 * d as u64
+        )));
+    }
+    Ok(PirQuery {
+        query: bytes,
+        num: query.num,
+    })
+}
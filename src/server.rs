@@ -1,13 +1,26 @@
-use super::{PirQuery, PirReply};
-use libc;
+use super::params::PirParameters;
+use super::seeded;
+use super::{check, check_input, key_digest, ClientHandle, Error, PirQuery, PirReply, Result};
+use bytemuck::Pod;
+use libc::{self, c_char};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
 use std::mem;
+use std::ptr;
 use std::slice;
 
-extern "C" {
-    fn new_parameters(ele_num: u32, ele_size: u32, N: u32, logt: u32, d: u32) -> *mut libc::c_void;
-    fn delete_parameters(params: *mut libc::c_void);
+mod capacity;
+mod keys;
+mod mapped;
+mod persist;
+mod stream;
 
-    fn new_pir_server(params: *const libc::c_void) -> *mut libc::c_void;
+extern "C" {
+    fn new_pir_server(
+        params: *const libc::c_void,
+        pir_server: &mut *mut libc::c_void,
+        error: &mut *mut c_char,
+    ) -> i32;
     fn delete_pir_server(pir_server: *mut libc::c_void);
 
     fn set_galois_key(
@@ -15,24 +28,29 @@ extern "C" {
         galois_key: *const u8,
         key_size: u32,
         client_id: u32,
-    );
+        error: &mut *mut c_char,
+    ) -> i32;
 
     fn set_database(
         pir_server: *mut libc::c_void,
         database: *const u8,
         ele_num: u32,
         ele_size: u32,
-    );
+        error: &mut *mut c_char,
+    ) -> i32;
 
-    fn update_database(
+    fn update_plaintext_elements(
         pir_server: *mut libc::c_void,
-        database: *const u8,
-        ele_num: u32,
-        ele_size: u32,
-        ele_index: u32,
-    );
+        params: *const libc::c_void,
+        index: u64,
+        offsets: *const u64,
+        count: u64,
+        bytes: *const u8,
+        ele_size: u64,
+        error: &mut *mut c_char,
+    ) -> i32;
 
-    fn preprocess_db(pir_server: *mut libc::c_void);
+    fn preprocess_db(pir_server: *mut libc::c_void, error: &mut *mut c_char) -> i32;
 
     fn generate_reply(
         pir_server: *const libc::c_void,
@@ -40,149 +58,440 @@ extern "C" {
         query: *const u8,
         query_size: u32,
         query_num: u32,
+        reply: &mut *mut u8,
         reply_size: &mut u32,
         reply_num: &mut u32,
+        key_server: *const libc::c_void,
         client_id: u32,
-    ) -> *mut u8;
+        error: &mut *mut c_char,
+    ) -> i32;
 }
 
+/// Serves one database.
+///
+/// `PirServer` is `Send` and `Sync`: `gen_reply` takes `&self` and may be called from
+/// several threads at once, e.g. on a server shared through an `Arc`. Setting up the
+/// database and registering keys take `&mut self`, so they never run concurrently with
+/// replies.
 pub struct PirServer {
-    server: *mut libc::c_void,
-    params: *mut libc::c_void,
+    // one per column
+    servers: Vec<*mut libc::c_void>,
+    params: PirParameters,
     ele_num: u32,
     ele_size: u32,
+    column_size: u32,
+    d: u32,
+    keys: keys::KeyStore,
+    is_set_up: bool,
+    // slots below `used` have been filled, except the deleted ones in `free`
+    used: u32,
+    free: BTreeSet<u32>,
+    // set when serving a file opened with `open_mapped`
+    mapped: Option<mapped::MappedDb>,
 }
 
+// `generate_reply` only reads the C++ server: the database is preprocessed (NTT form)
+// before any reply is generated and is not modified afterwards, and the bindings look up
+// the Galois key of the client with `std::map::find` instead of SealPIR's `operator[]`,
+// which may insert. The last use of the key is recorded atomically.
+// `generate_reply_mapped` reads the same state plus the read-only mapping. Every other
+// binding that modifies the server requires `&mut self`.
+unsafe impl Send for PirServer {}
+unsafe impl Sync for PirServer {}
+
 impl Drop for PirServer {
     fn drop(&mut self) {
-        unsafe {
-            delete_pir_server(self.server);
-            delete_parameters(self.params);
+        for &server in self.servers.iter() {
+            unsafe {
+                delete_pir_server(server);
+            }
         }
     }
 }
 
 impl PirServer {
-    pub fn new(
-        ele_num: u32,
-        ele_size: u32,
-        poly_degree: u32,
-        log_plain_mod: u32,
-        d: u32,
-    ) -> PirServer {
+    pub fn new(params: &PirParameters) -> Result<PirServer> {
+        let mut server = PirServer {
+            servers: Vec::with_capacity(params.num_columns() as usize),
+            params: params.clone(),
+            ele_num: params.ele_num(),
+            ele_size: params.ele_size(),
+            column_size: params.column_size(),
+            d: params.d(),
+            keys: keys::KeyStore::default(),
+            is_set_up: false,
+            used: params.ele_num(),
+            free: BTreeSet::new(),
+            mapped: None,
+        };
 
-        let params: *mut libc::c_void =
-            unsafe { new_parameters(ele_num, ele_size, poly_degree, log_plain_mod, d) };
+        // pushed one by one so that Drop frees those created before a failure
+        for _ in 0..params.num_columns() {
+            let mut error: *mut c_char = ptr::null_mut();
+            let mut server_ptr: *mut libc::c_void = ptr::null_mut();
+            unsafe {
+                let status = new_pir_server(params.as_ptr(), &mut server_ptr, &mut error);
+                check(status, error)?;
+            }
+            server.servers.push(server_ptr);
+        }
 
-        let server_ptr: *mut libc::c_void = unsafe { new_pir_server(params) };
+        Ok(server)
+    }
 
-        PirServer {
-            server: server_ptr,
-            params,
-            ele_num,
-            ele_size,
-        }
+    pub fn params(&self) -> &PirParameters {
+        &self.params
     }
 
-    pub fn setup<T>(&mut self, collection: &[T]) {
-        assert_eq!(collection.len(), self.ele_num as usize);
-        assert_eq!(mem::size_of::<T>(), self.ele_size as usize);
+    /// Sets up the database from elements of type `T`, which must be exactly `ele_size`
+    /// bytes. `Pod` types have no padding, so every byte stored is initialized.
+    pub fn setup<T: Pod>(&mut self, collection: &[T]) -> Result<()> {
+        self.check_collection(collection.len(), mem::size_of::<T>())?;
+        self.set_and_preprocess(bytemuck::cast_slice(collection))
+    }
 
-        unsafe {
-            set_database(
-                self.server,
-                collection.as_ptr() as *const u8,
-                self.ele_num,
-                self.ele_size,
-            );
+    pub fn setup_bytes(&mut self, collection: &[u8], element_size: usize) -> Result<()> {
+        self.check_collection_bytes(collection.len(), element_size)?;
+        self.set_and_preprocess(collection)
+    }
 
-            preprocess_db(self.server);
+    pub fn update<T: Pod>(&mut self, collection: &[T], index: usize) -> Result<()> {
+        self.check_collection(collection.len(), mem::size_of::<T>())?;
+        self.check_index(index)?;
+        let element = &bytemuck::cast_slice(collection)[index * self.ele_size as usize..];
+        self.update_elements(&[(index, &element[..self.ele_size as usize])])
+    }
+
+    pub fn update_bytes(
+        &mut self,
+        collection: &[u8],
+        element_size: usize,
+        index: usize,
+    ) -> Result<()> {
+        self.check_collection_bytes(collection.len(), element_size)?;
+        self.check_index(index)?;
+        let element = &collection[index * element_size..];
+        self.update_elements(&[(index, &element[..element_size])])
+    }
+
+    /// Replaces the elements at the given indices. Only the plaintexts holding them are
+    /// transformed again, so the cost grows with the number of updates rather than with
+    /// the size of the database. If an index appears more than once, the last update wins.
+    ///
+    /// Every update is validated before any is applied.
+    pub fn update_elements<B: AsRef<[u8]>>(&mut self, updates: &[(usize, B)]) -> Result<()> {
+        if self.mapped.is_some() {
+            return Err(Error::ReadOnlyDatabase);
+        }
+        if !self.is_set_up {
+            return Err(Error::DatabaseNotSet);
         }
+        for (index, element) in updates.iter() {
+            self.check_index(*index)?;
+            if element.as_ref().len() != self.ele_size as usize {
+                return Err(Error::SizeMismatch {
+                    expected: self.ele_size as usize,
+                    actual: element.as_ref().len(),
+                });
+            }
+        }
+
+        // the updates of each plaintext, in order
+        let per_plaintext = self.params.elements_per_plaintext() as usize;
+        let mut dirty: BTreeMap<usize, Vec<(usize, &[u8])>> = BTreeMap::new();
+        for (index, element) in updates.iter() {
+            dirty
+                .entry(index / per_plaintext)
+                .or_default()
+                .push((index % per_plaintext, element.as_ref()));
+        }
+
+        let ele_size = self.ele_size as usize;
+        let column_size = self.column_size as usize;
+        let mut offsets = Vec::new();
+        let mut bytes = Vec::new();
+
+        for (column, &server) in self.servers.iter().enumerate() {
+            let start = column * column_size;
+            let end = (start + column_size).min(ele_size);
+
+            for (&plaintext, elements) in dirty.iter() {
+                offsets.clear();
+                bytes.clear();
+                for &(position, element) in elements.iter() {
+                    offsets.push((position * column_size) as u64);
+                    // the last column is zero-padded
+                    bytes.extend_from_slice(&element[start..end]);
+                    bytes.resize(bytes.len() + column_size - (end - start), 0);
+                }
+
+                unsafe {
+                    let mut error: *mut c_char = ptr::null_mut();
+                    let status = update_plaintext_elements(
+                        server,
+                        self.params.as_ptr(),
+                        plaintext as u64,
+                        offsets.as_ptr(),
+                        offsets.len() as u64,
+                        bytes.as_ptr(),
+                        column_size as u64,
+                        &mut error,
+                    );
+                    check(status, error)?;
+                }
+            }
+        }
+
+        Ok(())
     }
 
-    pub fn setup_bytes(&mut self, collection: &[u8], element_size: usize) {
-        assert_eq!(collection.len() / element_size, self.ele_num as usize);
-        assert_eq!(element_size, self.ele_size as usize);
+    /// Registers a Galois key and returns the handle to answer its client's queries with.
+    /// See `open_key_store` and `set_key_memory_budget` for how long it is kept. Fails
+    /// with `KeyConflict` rather than replace a different key registered, in memory or in
+    /// the key store, under the same handle.
+    pub fn set_galois_key(&mut self, key: &[u8]) -> Result<ClientHandle> {
+        // seeded keys are registered, stored and identified in full form
+        let expanded;
+        let key = if seeded::is_seeded(key) {
+            expanded = seeded::expand_key(&self.params, key)?;
+            &expanded[..]
+        } else {
+            key
+        };
+
+        let digest = key_digest(key);
+        let client = ClientHandle::for_digest(&digest);
+        self.check_conflict(client, &digest)?;
+        let client_id = self.keys.id_for(client);
+        self.register_key(key, client_id)?;
+        self.key_registered(key, client, client_id, digest)?;
+        Ok(client)
+    }
+
+    // hands the key to the first column server under the bindings' id; every column
+    // replies with that copy
+    fn register_key(&mut self, key: &[u8], client_id: u32) -> Result<()> {
+        if key.is_empty() {
+            return Err(Error::MalformedCiphertext("empty Galois key".to_string()));
+        }
 
         unsafe {
-            set_database(
-                self.server,
-                collection.as_ptr() as *const u8,
-                self.ele_num,
-                self.ele_size,
+            let mut error: *mut c_char = ptr::null_mut();
+            let status = set_galois_key(
+                self.servers[0],
+                key.as_ptr(),
+                key.len() as u32,
+                client_id,
+                &mut error,
             );
+            check_input(status, error)?;
+        }
+
+        Ok(())
+    }
+
+    /// Answers `query` with the Galois key of `client`, which must be in memory: a key
+    /// that is only in the key store fails with `KeyNotLoaded` until `load_galois_key` or
+    /// `load_all_galois_keys` is called, which needs `&mut self` and so cannot happen
+    /// here.
+    #[inline]
+    pub fn gen_reply(&self, query: &PirQuery, client: ClientHandle) -> Result<PirReply> {
+        self.gen_reply_with_keys(query, client, self)
+    }
+
+    // answers `query` with the Galois key of `client` registered at `keys`, a server whose
+    // keys fit these parameters, so that several servers can share one copy of each key
+    pub(crate) fn gen_reply_with_keys(
+        &self,
+        query: &PirQuery,
+        client: ClientHandle,
+        keys: &PirServer,
+    ) -> Result<PirReply> {
+        if !self.is_set_up {
+            return Err(Error::DatabaseNotSet);
+        }
+        if !keys.params.shares_keys_with(&self.params) {
+            return Err(Error::InvalidParameters(
+                "the Galois keys were registered for different parameters".to_string(),
+            ));
+        }
+
+        let client_id = keys
+            .keys
+            .touch(client)
+            .ok_or_else(|| keys.keys.missing(client))?;
+
+        let expanded;
+        let query = if seeded::is_seeded(&query.query) {
+            expanded = seeded::expand_query(&self.params, query, self.d)?;
+            &expanded
+        } else {
+            query
+        };
+
+        // a query carries `num` ciphertexts for each of the d dimensions
+        let ciphertexts = query.num as usize * self.d as usize;
+        if ciphertexts == 0
+            || query.query.is_empty()
+            || !query.query.len().is_multiple_of(ciphertexts)
+        {
+            return Err(Error::MalformedCiphertext(format!(
+                "{} bytes cannot hold {} ciphertexts",
+                query.query.len(),
+                ciphertexts
+            )));
+        }
+
+        // every column answers the same query; the replies are concatenated in column
+        // order
+        let mut reply = PirReply {
+            reply: Vec::new(),
+            num: 0,
+        };
+
+        let key_server = keys.servers[0];
+        for (column, &server) in self.servers.iter().enumerate() {
+            let mut error: *mut c_char = ptr::null_mut();
+            let mut reply_size: u32 = 0;
+            let mut reply_num: u32 = 0;
+
+            unsafe {
+                let mut ptr: *mut u8 = ptr::null_mut();
+                let status = match &self.mapped {
+                    Some(db) => db.generate_reply(
+                        server,
+                        &self.params,
+                        column,
+                        query,
+                        &mut ptr,
+                        &mut reply_size,
+                        &mut reply_num,
+                        key_server,
+                        client_id,
+                        &mut error,
+                    ),
+                    None => generate_reply(
+                        server,
+                        self.params.as_ptr(),
+                        query.query.as_ptr(),
+                        query.query.len() as u32,
+                        query.num,
+                        &mut ptr,
+                        &mut reply_size,
+                        &mut reply_num,
+                        key_server,
+                        client_id,
+                        &mut error,
+                    ),
+                };
+                check_input(status, error)?;
 
-            preprocess_db(self.server);
+                reply
+                    .reply
+                    .extend_from_slice(slice::from_raw_parts(ptr, reply_size as usize));
+                libc::free(ptr as *mut libc::c_void);
+            }
+            reply.num += reply_num;
         }
+
+        Ok(reply)
     }
 
-    pub fn update<T>(&mut self, collection: &[T], index: usize) {
-        assert_eq!(collection.len(), self.ele_num as usize);
-        assert_eq!(mem::size_of::<T>(), self.ele_size as usize);
-        assert!(index < collection.len());
+    // `database` must hold ele_num * ele_size bytes
+    fn set_and_preprocess(&mut self, database: &[u8]) -> Result<()> {
+        // a failure below may leave a partially set database behind
+        self.is_set_up = false;
+        self.mapped = None;
+        self.used = self.ele_num;
+        self.free.clear();
 
-        unsafe {
-            update_database(
-                self.server,
-                collection.as_ptr() as *const u8,
-                self.ele_num,
-                self.ele_size,
-                index as u32,
-            );
+        for (column, &server) in self.servers.iter().enumerate() {
+            let data = self.column(database, column);
+            unsafe {
+                let mut error: *mut c_char = ptr::null_mut();
+                let status = set_database(
+                    server,
+                    data.as_ptr(),
+                    self.ele_num,
+                    self.column_size,
+                    &mut error,
+                );
+                check(status, error)?;
 
-            preprocess_db(self.server);
+                let status = preprocess_db(server, &mut error);
+                check(status, error)?;
+            }
         }
+
+        self.is_set_up = true;
+        Ok(())
     }
 
-    pub fn update_bytes(&mut self, collection: &[u8], element_size: usize, index: usize) {
-        assert_eq!(collection.len() / element_size, self.ele_num as usize);
-        assert_eq!(element_size, self.ele_size as usize);
-        assert!(index < collection.len());
+    // the chunk of every element stored in `column`, with the last column zero-padded
+    fn column<'a>(&self, database: &'a [u8], column: usize) -> Cow<'a, [u8]> {
+        if self.servers.len() == 1 {
+            return Cow::Borrowed(database);
+        }
 
-        unsafe {
-            update_database(
-                self.server,
-                collection.as_ptr() as *const u8,
-                self.ele_num,
-                self.ele_size,
-                index as u32,
-            );
+        let ele_size = self.ele_size as usize;
+        let column_size = self.column_size as usize;
+        let start = column * column_size;
+        let end = (start + column_size).min(ele_size);
 
-            preprocess_db(self.server);
+        let mut data = vec![0u8; self.ele_num as usize * column_size];
+        for (chunk, element) in data
+            .chunks_exact_mut(column_size)
+            .zip(database.chunks_exact(ele_size))
+        {
+            chunk[..end - start].copy_from_slice(&element[start..end]);
         }
+        Cow::Owned(data)
     }
 
-    pub fn set_galois_key(&mut self, key: &[u8], client_id: u32) {
-        unsafe {
-            set_galois_key(self.server, key.as_ptr(), key.len() as u32, client_id);
+    fn check_collection(&self, len: usize, element_size: usize) -> Result<()> {
+        if element_size != self.ele_size as usize {
+            return Err(Error::SizeMismatch {
+                expected: self.ele_size as usize,
+                actual: element_size,
+            });
+        }
+
+        if len != self.ele_num as usize {
+            return Err(Error::SizeMismatch {
+                expected: self.ele_num as usize,
+                actual: len,
+            });
         }
+
+        Ok(())
     }
 
-    #[inline]
-    pub fn gen_reply(&self, query: &PirQuery, client_id: u32) -> PirReply {
-        let mut reply_size: u32 = 0;
-        let mut reply_num: u32 = 0;
-
-        let reply: Vec<u8> = unsafe {
-            let ptr = generate_reply(
-                self.server,
-                self.params,
-                query.query.as_ptr(),
-                query.query.len() as u32,
-                query.num,
-                &mut reply_size,
-                &mut reply_num,
-                client_id,
-            );
+    fn check_collection_bytes(&self, len: usize, element_size: usize) -> Result<()> {
+        if element_size != self.ele_size as usize {
+            return Err(Error::SizeMismatch {
+                expected: self.ele_size as usize,
+                actual: element_size,
+            });
+        }
 
-            let ans = slice::from_raw_parts_mut(ptr as *mut u8, reply_size as usize).to_vec();
-            libc::free(ptr as *mut libc::c_void);
-            ans
-        };
+        let expected = self.ele_num as usize * self.ele_size as usize;
+        if len != expected {
+            return Err(Error::SizeMismatch {
+                expected,
+                actual: len,
+            });
+        }
+
+        Ok(())
+    }
 
-        PirReply {
-            reply,
-            num: reply_num,
+    fn check_index(&self, index: usize) -> Result<()> {
+        if index >= self.ele_num as usize {
+            return Err(Error::IndexOutOfRange {
+                index,
+                len: self.ele_num as usize,
+            });
         }
+        Ok(())
     }
 }
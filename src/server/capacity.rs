@@ -0,0 +1,180 @@
+// Using `ele_num` as a capacity: elements are appended into free slots and deleted ones
+// are tombstoned, so the database changes without rebuilding the server or the clients'
+// parameters. Only `resize` changes the parameters, and it moves them to the next epoch.
+//
+// Slots keep their index for as long as they are in use, so clients can keep addressing
+// elements by index across appends, deletions and resizes.
+
+use libc::{self, c_char};
+use std::mem;
+use std::ptr;
+
+use super::persist::{
+    get_preprocessed_db, get_preprocessed_plaintext, new_preprocessed_db,
+    set_preprocessed_plaintext,
+};
+use super::stream::set_plaintext_elements;
+use super::PirServer;
+use crate::{check, Error, Result};
+
+extern "C" {
+    fn copy_galois_keys(
+        from: *const libc::c_void,
+        to: *mut libc::c_void,
+        error: &mut *mut c_char,
+    ) -> i32;
+}
+
+impl PirServer {
+    /// Sets up a database with every slot free, to be filled with `append`. What a query
+    /// for a free slot returns is unspecified.
+    pub fn setup_empty(&mut self) -> Result<()> {
+        self.ingest(|_, buf| {
+            buf.iter_mut().for_each(|b| *b = 0);
+            Ok(())
+        })?;
+        self.used = 0;
+        Ok(())
+    }
+
+    /// Number of slots in use. A database set up with `setup` and its variants is full.
+    pub fn len(&self) -> usize {
+        self.used as usize - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of slots, i.e., `ele_num` of the current parameters.
+    pub fn capacity(&self) -> usize {
+        self.ele_num as usize
+    }
+
+    /// Whether `append` would fail with `CapacityExceeded`, meaning the database has to
+    /// be resized before anything else can be added.
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Stores `element` in a free slot, preferring the lowest deleted one, and returns the
+    /// index of the slot.
+    ///
+    /// `update_elements` may write to any slot, but does not mark it as used.
+    pub fn append(&mut self, element: &[u8]) -> Result<usize> {
+        let index = match self.free.iter().next() {
+            Some(&index) => index,
+            None if self.used < self.ele_num => self.used,
+            None => {
+                return Err(Error::CapacityExceeded {
+                    capacity: self.capacity(),
+                })
+            }
+        };
+
+        self.update_elements(&[(index as usize, element)])?;
+        if !self.free.remove(&index) {
+            self.used += 1;
+        }
+        Ok(index as usize)
+    }
+
+    /// Zeroes the element at `index` and frees its slot. Deleting a free slot does nothing.
+    pub fn delete(&mut self, index: usize) -> Result<()> {
+        self.check_index(index)?;
+        if index >= self.used as usize || self.free.contains(&(index as u32)) {
+            return Ok(());
+        }
+
+        let zeros = vec![0u8; self.ele_size as usize];
+        self.update_elements(&[(index, zeros)])?;
+        self.free.insert(index as u32);
+
+        // free slots at the end are not tracked
+        while self.used > 0 && self.free.remove(&(self.used - 1)) {
+            self.used -= 1;
+        }
+        Ok(())
+    }
+
+    /// Changes the capacity to `capacity` slots, which must hold every slot in use.
+    /// Elements keep their indices and registered Galois keys stay valid, but the
+    /// parameters move to the next epoch: clients have to be rebuilt from `params()`.
+    pub fn resize(&mut self, capacity: u32) -> Result<()> {
+        if self.mapped.is_some() {
+            return Err(Error::ReadOnlyDatabase);
+        }
+        if !self.is_set_up {
+            return Err(Error::DatabaseNotSet);
+        }
+        if capacity < self.used {
+            return Err(Error::InvalidParameters(format!(
+                "capacity {} cannot hold the element at index {}",
+                capacity,
+                self.used - 1
+            )));
+        }
+
+        let params = self.params.resized(capacity)?;
+        let mut resized = PirServer::new(&params)?;
+        unsafe {
+            let mut error: *mut c_char = ptr::null_mut();
+            let status = copy_galois_keys(self.servers[0], resized.servers[0], &mut error);
+            check(status, error)?;
+        }
+
+        // Plaintexts are packed the same way for every capacity, since the size of the
+        // elements does not change: they are copied as they are, and the new ones are
+        // padding.
+        let num_plaintexts: u64 = params.nvec().iter().product();
+        for (&from, &to) in self.servers.iter().zip(resized.servers.iter()) {
+            unsafe {
+                let mut error: *mut c_char = ptr::null_mut();
+                let mut old_plaintexts: u64 = 0;
+                let mut coeff_count: u64 = 0;
+                let status =
+                    get_preprocessed_db(from, &mut old_plaintexts, &mut coeff_count, &mut error);
+                check(status, error)?;
+
+                let status = new_preprocessed_db(to, num_plaintexts, &mut error);
+                check(status, error)?;
+
+                for index in 0..num_plaintexts {
+                    if index < old_plaintexts {
+                        let mut coeffs: *const u64 = ptr::null();
+                        let status =
+                            get_preprocessed_plaintext(from, index, &mut coeffs, &mut error);
+                        check(status, error)?;
+
+                        let status = set_preprocessed_plaintext(
+                            to,
+                            params.as_ptr(),
+                            index,
+                            coeffs,
+                            coeff_count,
+                            &mut error,
+                        );
+                        check(status, error)?;
+                    } else {
+                        let status = set_plaintext_elements(
+                            to,
+                            params.as_ptr(),
+                            index,
+                            ptr::null(),
+                            0,
+                            &mut error,
+                        );
+                        check(status, error)?;
+                    }
+                }
+            }
+        }
+
+        resized.keys = mem::take(&mut self.keys);
+        resized.used = self.used;
+        resized.free = mem::take(&mut self.free);
+        resized.is_set_up = true;
+        *self = resized;
+        Ok(())
+    }
+}
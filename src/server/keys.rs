@@ -0,0 +1,444 @@
+// Registered Galois keys. The first column server holds the copy every column replies with.
+// Keys are large, so resident keys can be evicted when they have not been used for a while
+// (TTL) or, least recently used first, when they exceed a memory budget. Keys written to a key store
+// directory survive eviction and restarts: `load_galois_key` makes them resident again.
+//
+// The bindings identify keys by a u32 id, which is assigned here to each client handle.
+//
+// Key file layout (`<client handle>.key` in the key store), all integers little-endian:
+//   magic (8 bytes), format version (u32)
+//   poly_degree, log_plain_mod (u32 each)
+//   key length (u64), serialized key
+//   checksum (u64) of everything above
+
+use libc::{self, c_char};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use super::PirServer;
+use crate::hash::hash64;
+use crate::{check, key_digest, ClientHandle, Error, KeyDigest, Result};
+
+extern "C" {
+    fn remove_galois_key(
+        pir_server: *mut libc::c_void,
+        client_id: u32,
+        error: &mut *mut c_char,
+    ) -> i32;
+}
+
+const MAGIC: [u8; 8] = *b"SEALPIRK";
+
+// bumped whenever the layout above changes
+const FORMAT_VERSION: u32 = 1;
+
+const CHECKSUM_SEED: u64 = 0;
+
+// bytes before the key
+const HEADER_SIZE: usize = 8 + 4 + 4 + 4 + 8;
+
+// bytes after the key
+const CHECKSUM_SIZE: usize = 8;
+
+pub(super) struct KeyStore {
+    resident: HashMap<ClientHandle, Resident>,
+    // clients with a file in `dir`
+    stored: HashSet<ClientHandle>,
+    dir: Option<PathBuf>,
+    budget: Option<usize>,
+    ttl: Option<Duration>,
+    start: Instant,
+    next_id: u32,
+}
+
+struct Resident {
+    // id of the key in the bindings
+    id: u32,
+    // bytes held by the first column server
+    size: usize,
+    // tells the key apart from other keys with the same handle
+    digest: KeyDigest,
+    // milliseconds since `start`, updated by concurrent replies
+    last_used: AtomicU64,
+}
+
+impl Default for KeyStore {
+    fn default() -> KeyStore {
+        KeyStore {
+            resident: HashMap::new(),
+            stored: HashSet::new(),
+            dir: None,
+            budget: None,
+            ttl: None,
+            start: Instant::now(),
+            next_id: 0,
+        }
+    }
+}
+
+impl KeyStore {
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    // records a use of the key of `client` and returns its id, if it is resident
+    pub(super) fn touch(&self, client: ClientHandle) -> Option<u32> {
+        self.resident.get(&client).map(|resident| {
+            resident.last_used.store(self.now(), Ordering::Relaxed);
+            resident.id
+        })
+    }
+
+    // the id of the resident key of `client`, or one that no resident key uses: once the
+    // counter wraps, registering under the id of another client would replace its key
+    pub(super) fn id_for(&mut self, client: ClientHandle) -> u32 {
+        if let Some(resident) = self.resident.get(&client) {
+            return resident.id;
+        }
+        loop {
+            let id = self.next_id;
+            self.next_id = self.next_id.wrapping_add(1);
+            if !self.resident.values().any(|resident| resident.id == id) {
+                return id;
+            }
+        }
+    }
+
+    // the error for a client whose key is not resident
+    pub(super) fn missing(&self, client: ClientHandle) -> Error {
+        if self.stored.contains(&client) {
+            Error::KeyNotLoaded(client)
+        } else {
+            Error::UnknownClient(client)
+        }
+    }
+
+    fn path(&self, client: ClientHandle) -> Option<PathBuf> {
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.key", client)))
+    }
+}
+
+impl PirServer {
+    /// Whether the Galois key of `client` is registered, in memory or in the key store.
+    /// `gen_reply` only uses keys in memory; see `load_galois_key`.
+    pub fn has_client(&self, client: ClientHandle) -> bool {
+        self.keys.resident.contains_key(&client) || self.keys.stored.contains(&client)
+    }
+
+    /// Number of clients with a registered Galois key, in memory or in the key store.
+    pub fn client_count(&self) -> usize {
+        let unstored = self
+            .keys
+            .resident
+            .keys()
+            .filter(|client| !self.keys.stored.contains(client))
+            .count();
+        unstored + self.keys.stored.len()
+    }
+
+    /// Bytes of Galois keys held in memory.
+    pub fn key_memory_usage(&self) -> usize {
+        self.keys.resident.values().map(|r| r.size).sum()
+    }
+
+    /// Forgets the Galois key of `client`, also removing it from the key store. Returns
+    /// whether it was registered.
+    pub fn remove_galois_key(&mut self, client: ClientHandle) -> Result<bool> {
+        let registered = self.has_client(client);
+
+        if self.keys.resident.contains_key(&client) {
+            self.unload_key(client)?;
+        }
+        if self.keys.stored.remove(&client) {
+            if let Some(path) = self.keys.path(client) {
+                fs::remove_file(path)?;
+            }
+        }
+
+        Ok(registered)
+    }
+
+    /// Bounds the memory held by Galois keys: when registering or loading a key exceeds
+    /// `budget` bytes, the least recently used keys are evicted. The key just registered
+    /// is kept even if it alone exceeds the budget.
+    pub fn set_key_memory_budget(&mut self, budget: Option<usize>) -> Result<()> {
+        self.keys.budget = budget;
+        self.evict_keys(None)
+    }
+
+    /// Evicts Galois keys that have not been used by `gen_reply` for `ttl`. Expired keys
+    /// are evicted whenever a key is registered or loaded, and by `evict_expired_keys`.
+    pub fn set_key_ttl(&mut self, ttl: Option<Duration>) -> Result<()> {
+        self.keys.ttl = ttl;
+        self.evict_keys(None)
+    }
+
+    /// Evicts the Galois keys that have expired, e.g. to call periodically on a server
+    /// that rarely registers keys.
+    pub fn evict_expired_keys(&mut self) -> Result<()> {
+        self.evict_keys(None)
+    }
+
+    /// Keeps the Galois keys registered from now on in `dir` as well, so that they are not
+    /// lost when evicted or when the server restarts. Keys already in `dir` are registered
+    /// but stay on disk until `load_galois_key` is called for them; keys registered
+    /// before are not written.
+    ///
+    /// `gen_reply` fails with `KeyNotLoaded` for clients whose key is only in the store,
+    /// although `has_client` returns true for them: `load_galois_key` has to be called
+    /// first. Loading takes `&mut self`, so a server shared between threads cannot load
+    /// keys on demand; call `load_all_galois_keys` before sharing it.
+    pub fn open_key_store<P: AsRef<Path>>(&mut self, dir: P) -> Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        let mut stored = HashSet::new();
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            let handle = name
+                .to_str()
+                .and_then(|name| name.strip_suffix(".key"))
+                .filter(|handle| handle.len() == 16)
+                .and_then(|handle| u64::from_str_radix(handle, 16).ok());
+            if let Some(handle) = handle {
+                stored.insert(ClientHandle(handle));
+            }
+        }
+
+        self.keys.stored = stored;
+        self.keys.dir = Some(dir.to_path_buf());
+        Ok(())
+    }
+
+    /// Makes the Galois key of `client` in the key store usable by `gen_reply` again.
+    /// Returns false if the client has no key in memory or in the store.
+    pub fn load_galois_key(&mut self, client: ClientHandle) -> Result<bool> {
+        if self.keys.touch(client).is_some() {
+            return Ok(true);
+        }
+        let path = match self.keys.path(client) {
+            Some(path) if self.keys.stored.contains(&client) => path,
+            _ => return Ok(false),
+        };
+
+        let file = fs::read(path)?;
+        let key = self.parse_key_file(&file)?;
+        let digest = key_digest(key);
+        if ClientHandle::for_digest(&digest) != client {
+            return Err(Error::InvalidFile(
+                "key does not match its file name".to_string(),
+            ));
+        }
+
+        let client_id = self.keys.id_for(client);
+        self.register_key(key, client_id)?;
+        self.admit_key(client, client_id, key.len(), digest)?;
+        Ok(true)
+    }
+
+    /// Loads every key of the key store that is not in memory, e.g. at startup, before
+    /// the server is shared between threads, and returns how many were loaded. With a
+    /// memory budget, loading stops once the next key would exceed it.
+    pub fn load_all_galois_keys(&mut self) -> Result<usize> {
+        let mut clients: Vec<ClientHandle> = self
+            .keys
+            .stored
+            .iter()
+            .filter(|client| !self.keys.resident.contains_key(client))
+            .copied()
+            .collect();
+        clients.sort_unstable();
+
+        let mut loaded = 0;
+        let mut key_size = 0;
+        for client in clients {
+            if let Some(budget) = self.keys.budget {
+                if self.key_memory_usage() + key_size > budget {
+                    break;
+                }
+            }
+            self.load_galois_key(client)?;
+            key_size = self.keys.resident.get(&client).map_or(0, |r| r.size);
+            loaded += 1;
+        }
+        Ok(loaded)
+    }
+
+    // fails if a key other than the one hashing to `digest` is registered for `client`;
+    // handles are short enough that a collision, though costly, can be searched for
+    pub(super) fn check_conflict(&self, client: ClientHandle, digest: &KeyDigest) -> Result<()> {
+        let registered = match self.keys.resident.get(&client) {
+            Some(resident) => resident.digest,
+            None => match self.keys.path(client) {
+                Some(path) if self.keys.stored.contains(&client) => {
+                    let file = fs::read(path)?;
+                    key_digest(self.parse_key_file(&file)?)
+                }
+                _ => return Ok(()),
+            },
+        };
+
+        if registered != *digest {
+            return Err(Error::KeyConflict(client));
+        }
+        Ok(())
+    }
+
+    // called by `set_galois_key` once the first column server holds the key
+    pub(super) fn key_registered(
+        &mut self,
+        key: &[u8],
+        client: ClientHandle,
+        client_id: u32,
+        digest: KeyDigest,
+    ) -> Result<()> {
+        // replaced below, or dropped if the key cannot be stored
+        self.keys.resident.remove(&client);
+        if let Some(path) = self.keys.path(client) {
+            if let Err(e) = self.write_key_file(&path, key) {
+                self.unload_id(client_id)?;
+                return Err(e);
+            }
+            self.keys.stored.insert(client);
+        }
+        self.admit_key(client, client_id, key.len(), digest)
+    }
+
+    fn admit_key(
+        &mut self,
+        client: ClientHandle,
+        client_id: u32,
+        key_size: usize,
+        digest: KeyDigest,
+    ) -> Result<()> {
+        let resident = Resident {
+            id: client_id,
+            size: key_size,
+            digest,
+            last_used: AtomicU64::new(self.keys.now()),
+        };
+        self.keys.resident.insert(client, resident);
+        self.evict_keys(Some(client))
+    }
+
+    // applies the TTL and the memory budget to every resident key except `keep`
+    fn evict_keys(&mut self, keep: Option<ClientHandle>) -> Result<()> {
+        if let Some(ttl) = self.keys.ttl {
+            let now = self.keys.now();
+            let ttl = ttl.as_millis() as u64;
+            let expired: Vec<ClientHandle> = self
+                .keys
+                .resident
+                .iter()
+                .filter(|&(&id, resident)| {
+                    Some(id) != keep
+                        && now.saturating_sub(resident.last_used.load(Ordering::Relaxed)) > ttl
+                })
+                .map(|(&id, _)| id)
+                .collect();
+            for id in expired {
+                self.unload_key(id)?;
+            }
+        }
+
+        if let Some(budget) = self.keys.budget {
+            while self.key_memory_usage() > budget {
+                let lru = self
+                    .keys
+                    .resident
+                    .iter()
+                    .filter(|&(&id, _)| Some(id) != keep)
+                    .min_by_key(|(_, resident)| resident.last_used.load(Ordering::Relaxed))
+                    .map(|(&id, _)| id);
+                match lru {
+                    Some(id) => self.unload_key(id)?,
+                    None => break,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // removes the key of `client` from memory, but not from the key store
+    fn unload_key(&mut self, client: ClientHandle) -> Result<()> {
+        if let Some(resident) = self.keys.resident.remove(&client) {
+            self.unload_id(resident.id)?;
+        }
+        Ok(())
+    }
+
+    fn unload_id(&mut self, client_id: u32) -> Result<()> {
+        unsafe {
+            let mut error: *mut c_char = ptr::null_mut();
+            let status = remove_galois_key(self.servers[0], client_id, &mut error);
+            check(status, error)?;
+        }
+        Ok(())
+    }
+
+    fn write_key_file(&self, path: &Path, key: &[u8]) -> Result<()> {
+        let mut file = Vec::with_capacity(HEADER_SIZE + key.len() + CHECKSUM_SIZE);
+        file.extend_from_slice(&MAGIC);
+        file.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        file.extend_from_slice(&self.params.poly_degree().to_le_bytes());
+        file.extend_from_slice(&self.params.log_plain_mod().to_le_bytes());
+        file.extend_from_slice(&(key.len() as u64).to_le_bytes());
+        file.extend_from_slice(key);
+        let checksum = hash64(CHECKSUM_SEED, &file);
+        file.extend_from_slice(&checksum.to_le_bytes());
+
+        // a crash while writing leaves the previous file intact
+        let tmp = path.with_extension("key.tmp");
+        fs::write(&tmp, &file)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    // validates a key file and returns the key it holds
+    fn parse_key_file<'a>(&self, file: &'a [u8]) -> Result<&'a [u8]> {
+        let invalid = |msg: &str| Err(Error::InvalidFile(msg.to_string()));
+
+        if file.len() < HEADER_SIZE + CHECKSUM_SIZE {
+            return invalid("file is truncated");
+        }
+        let (data, checksum) = file.split_at(file.len() - CHECKSUM_SIZE);
+        if hash64(CHECKSUM_SEED, data).to_le_bytes() != checksum {
+            return invalid("checksum mismatch");
+        }
+
+        let u32_at = |at: usize| {
+            let mut bytes = [0u8; 4];
+            bytes.copy_from_slice(&data[at..at + 4]);
+            u32::from_le_bytes(bytes)
+        };
+        if data[..8] != MAGIC {
+            return invalid("not a Galois key");
+        }
+        if u32_at(8) != FORMAT_VERSION {
+            return Err(Error::InvalidFile(format!(
+                "unsupported format version {}",
+                u32_at(8)
+            )));
+        }
+        if u32_at(12) != self.params.poly_degree() || u32_at(16) != self.params.log_plain_mod() {
+            return Err(Error::InvalidParameters(
+                "the key was saved with different parameters".to_string(),
+            ));
+        }
+
+        let mut len = [0u8; 8];
+        len.copy_from_slice(&data[20..28]);
+        let key = &data[HEADER_SIZE..];
+        if u64::from_le_bytes(len) != key.len() as u64 {
+            return invalid("key length does not match the file size");
+        }
+        Ok(key)
+    }
+}
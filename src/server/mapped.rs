@@ -0,0 +1,134 @@
+// Serving a database saved with `save_preprocessed` directly from the file. The file is
+// mapped read-only, so its pages live in the page cache: they are loaded on demand, can
+// be evicted under memory pressure and are shared by every process mapping the same file.
+
+use libc::{self, c_char};
+use memmap2::Mmap;
+use std::fs::File;
+use std::path::Path;
+
+use super::persist::{read_header, CHECKSUM_SIZE};
+use super::PirServer;
+use crate::params::PirParameters;
+use crate::{Error, PirQuery, Result};
+
+extern "C" {
+    fn generate_reply_mapped(
+        pir_server: *const libc::c_void,
+        params: *const libc::c_void,
+        db: *const u8,
+        num_plaintexts: u64,
+        coeff_count: u64,
+        query: *const u8,
+        query_size: u32,
+        query_num: u32,
+        reply: &mut *mut u8,
+        reply_size: &mut u32,
+        reply_num: &mut u32,
+        key_server: *const libc::c_void,
+        client_id: u32,
+        error: &mut *mut c_char,
+    ) -> i32;
+}
+
+pub(super) struct MappedDb {
+    map: Mmap,
+    // bytes before the coefficients
+    offset: usize,
+    num_plaintexts: u64,
+    coeff_count: u64,
+}
+
+impl MappedDb {
+    // the whole file
+    pub(super) fn bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    // calls `generate_reply_mapped` on the plaintexts of `column`
+    #[allow(clippy::too_many_arguments)]
+    pub(super) unsafe fn generate_reply(
+        &self,
+        server: *const libc::c_void,
+        params: &PirParameters,
+        column: usize,
+        query: &PirQuery,
+        reply: &mut *mut u8,
+        reply_size: &mut u32,
+        reply_num: &mut u32,
+        key_server: *const libc::c_void,
+        client_id: u32,
+        error: &mut *mut c_char,
+    ) -> i32 {
+        let column_bytes = self.column_bytes();
+        let db = &self.map[self.offset + column * column_bytes..][..column_bytes];
+
+        generate_reply_mapped(
+            server,
+            params.as_ptr(),
+            db.as_ptr(),
+            self.num_plaintexts,
+            self.coeff_count,
+            query.query.as_ptr(),
+            query.query.len() as u32,
+            query.num,
+            reply,
+            reply_size,
+            reply_num,
+            key_server,
+            client_id,
+            error,
+        )
+    }
+
+    fn column_bytes(&self) -> usize {
+        self.num_plaintexts as usize * self.coeff_count as usize * 8
+    }
+}
+
+impl PirServer {
+    /// Serves the database saved at `path` by `save_preprocessed` for the same `params`
+    /// without loading it into memory: `gen_reply` reads the plaintexts from a read-only
+    /// memory map of the file. The database cannot be updated, but `setup` replaces it
+    /// with an in-memory one. As with `load_preprocessed`, the parameters of the server
+    /// are in the epoch the database was saved in.
+    ///
+    /// Only the header and the size of the file are checked; `load_preprocessed` also
+    /// verifies the checksum. The file must not be modified while it is mapped.
+    pub fn open_mapped<P: AsRef<Path>>(path: P, params: &PirParameters) -> Result<PirServer> {
+        // coefficients are stored little-endian and read in place
+        if cfg!(target_endian = "big") {
+            return Err(Error::InvalidFile(
+                "memory-mapped databases require a little-endian host".to_string(),
+            ));
+        }
+
+        let file = File::open(path)?;
+        let map = unsafe { Mmap::map(&file)? };
+
+        let header = read_header(&mut &map[..], params)?;
+
+        let db = MappedDb {
+            map,
+            offset: header.size(),
+            num_plaintexts: header.num_plaintexts,
+            coeff_count: header.coeff_count,
+        };
+        let expected =
+            db.offset + params.num_columns() as usize * db.column_bytes() + CHECKSUM_SIZE;
+        if db.map.len() != expected {
+            return Err(Error::InvalidFile(format!(
+                "expected {} bytes, found {}",
+                expected,
+                db.map.len()
+            )));
+        }
+
+        let mut server = PirServer::new(&params.with_epoch(header.epoch)?)?;
+        server.used = header.used;
+        server.free = header.free;
+        server.mapped = Some(db);
+        server.is_set_up = true;
+        Ok(server)
+    }
+}
@@ -0,0 +1,353 @@
+// Saving and loading the preprocessed database, so that a restarted server does not have to
+// transform every plaintext again.
+//
+// File layout, all integers little-endian:
+//   magic (8 bytes), format version (u32)
+//   ele_num, ele_size, poly_degree, log_plain_mod, d (u32 each)
+//   num_columns (u32), plaintexts per column (u64), coefficients per plaintext (u64)
+//   epoch, used slots, free slot count (u32 each), the free slots (u32 each, ascending)
+//   the coefficients of every plaintext of every column (u64 each), column by column
+//   checksum (u64) of everything above
+// The checksum detects corruption, not tampering.
+
+use libc::{self, c_char};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::ptr;
+use std::slice;
+
+use super::PirServer;
+use crate::hash::Hasher64;
+use crate::params::PirParameters;
+use crate::{check, Error, Result};
+
+extern "C" {
+    pub(super) fn get_preprocessed_db(
+        pir_server: *const libc::c_void,
+        num_plaintexts: &mut u64,
+        coeff_count: &mut u64,
+        error: &mut *mut c_char,
+    ) -> i32;
+
+    pub(super) fn get_preprocessed_plaintext(
+        pir_server: *const libc::c_void,
+        index: u64,
+        coeffs: &mut *const u64,
+        error: &mut *mut c_char,
+    ) -> i32;
+
+    pub(super) fn new_preprocessed_db(
+        pir_server: *mut libc::c_void,
+        num_plaintexts: u64,
+        error: &mut *mut c_char,
+    ) -> i32;
+
+    pub(super) fn set_preprocessed_plaintext(
+        pir_server: *mut libc::c_void,
+        params: *const libc::c_void,
+        index: u64,
+        coeffs: *const u64,
+        coeff_count: u64,
+        error: &mut *mut c_char,
+    ) -> i32;
+}
+
+const MAGIC: [u8; 8] = *b"SEALPIRD";
+
+// bumped whenever the layout above changes
+const FORMAT_VERSION: u32 = 2;
+
+const CHECKSUM_SEED: u64 = 0;
+
+// bytes before the free slots, which precede the coefficients
+const HEADER_SIZE: usize = 8 + 4 + 5 * 4 + 4 + 8 + 8 + 3 * 4;
+
+// bytes after the coefficients
+pub(super) const CHECKSUM_SIZE: usize = 8;
+
+// shape and slot occupancy of the database stored in a file
+pub(super) struct Header {
+    pub(super) num_plaintexts: u64,
+    pub(super) coeff_count: u64,
+    pub(super) epoch: u32,
+    pub(super) used: u32,
+    pub(super) free: BTreeSet<u32>,
+}
+
+impl Header {
+    // bytes before the coefficients
+    pub(super) fn size(&self) -> usize {
+        HEADER_SIZE + 4 * self.free.len()
+    }
+}
+
+impl PirServer {
+    /// Writes the preprocessed database to `path`, to be restored with
+    /// `load_preprocessed`, along with the epoch of the parameters and which slots are in
+    /// use. Registered Galois keys are not saved.
+    pub fn save_preprocessed<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        if !self.is_set_up {
+            return Err(Error::DatabaseNotSet);
+        }
+
+        // a mapped database already is in this format
+        if let Some(db) = &self.mapped {
+            let mut out = File::create(path)?;
+            out.write_all(db.bytes())?;
+            return Ok(());
+        }
+
+        // every column has the same layout
+        let mut num_plaintexts: u64 = 0;
+        let mut coeff_count: u64 = 0;
+        unsafe {
+            let mut error: *mut c_char = ptr::null_mut();
+            let status = get_preprocessed_db(
+                self.servers[0],
+                &mut num_plaintexts,
+                &mut coeff_count,
+                &mut error,
+            );
+            check(status, error)?;
+        }
+
+        let mut out = Checksummed::new(BufWriter::new(File::create(path)?));
+        out.write_all(&MAGIC)?;
+        out.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        for value in spec_values(&self.params).iter() {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        out.write_all(&(self.servers.len() as u32).to_le_bytes())?;
+        out.write_all(&num_plaintexts.to_le_bytes())?;
+        out.write_all(&coeff_count.to_le_bytes())?;
+        out.write_all(&self.params.epoch().to_le_bytes())?;
+        out.write_all(&self.used.to_le_bytes())?;
+        out.write_all(&(self.free.len() as u32).to_le_bytes())?;
+        for &slot in self.free.iter() {
+            out.write_all(&slot.to_le_bytes())?;
+        }
+
+        let mut buf = Vec::with_capacity(coeff_count as usize * 8);
+        for &server in self.servers.iter() {
+            for index in 0..num_plaintexts {
+                let coeffs = unsafe {
+                    let mut error: *mut c_char = ptr::null_mut();
+                    let mut coeffs: *const u64 = ptr::null();
+                    let status = get_preprocessed_plaintext(server, index, &mut coeffs, &mut error);
+                    check(status, error)?;
+                    slice::from_raw_parts(coeffs, coeff_count as usize)
+                };
+
+                buf.clear();
+                for coeff in coeffs {
+                    buf.extend_from_slice(&coeff.to_le_bytes());
+                }
+                out.write_all(&buf)?;
+            }
+        }
+
+        let checksum = out.hasher.finish();
+        out.inner.write_all(&checksum.to_le_bytes())?;
+        out.inner.flush()?;
+        Ok(())
+    }
+
+    /// Restores a database saved with `save_preprocessed` for the same `params`, with its
+    /// free slots. The parameters of the server are moved to the epoch the database was
+    /// saved in, whatever the epoch of `params`; clients have to use `params()`. Galois
+    /// keys have to be registered again.
+    pub fn load_preprocessed<P: AsRef<Path>>(path: P, params: &PirParameters) -> Result<PirServer> {
+        let mut input = Checksummed::new(BufReader::new(File::open(path)?));
+        let header = read_header(&mut input, params)?;
+        let (num_plaintexts, coeff_count) = (header.num_plaintexts, header.coeff_count);
+
+        let mut server = PirServer::new(&params.with_epoch(header.epoch)?)?;
+        server.used = header.used;
+        server.free = header.free;
+
+        let mut buf = vec![0u8; coeff_count as usize * 8];
+        let mut coeffs = vec![0u64; coeff_count as usize];
+        for &column in server.servers.iter() {
+            unsafe {
+                let mut error: *mut c_char = ptr::null_mut();
+                let status = new_preprocessed_db(column, num_plaintexts, &mut error);
+                check(status, error)?;
+            }
+
+            for index in 0..num_plaintexts {
+                read_exact(&mut input, &mut buf)?;
+                for (coeff, bytes) in coeffs.iter_mut().zip(buf.chunks_exact(8)) {
+                    let mut le = [0u8; 8];
+                    le.copy_from_slice(bytes);
+                    *coeff = u64::from_le_bytes(le);
+                }
+
+                unsafe {
+                    let mut error: *mut c_char = ptr::null_mut();
+                    let status = set_preprocessed_plaintext(
+                        column,
+                        server.params.as_ptr(),
+                        index,
+                        coeffs.as_ptr(),
+                        coeff_count,
+                        &mut error,
+                    );
+                    check(status, error)?;
+                }
+            }
+        }
+
+        let checksum = input.hasher.finish();
+        let mut stored = [0u8; 8];
+        read_exact(&mut input.inner, &mut stored)?;
+        if u64::from_le_bytes(stored) != checksum {
+            return Err(Error::InvalidFile("checksum mismatch".to_string()));
+        }
+
+        server.is_set_up = true;
+        Ok(server)
+    }
+}
+
+// reads and validates the header of a file written by `save_preprocessed` for `params`
+pub(super) fn read_header<R: Read>(input: &mut R, params: &PirParameters) -> Result<Header> {
+    let mut magic = [0u8; 8];
+    read_exact(input, &mut magic)?;
+    if magic != MAGIC {
+        return Err(Error::InvalidFile(
+            "not a preprocessed database".to_string(),
+        ));
+    }
+
+    let version = read_u32(input)?;
+    if version != FORMAT_VERSION {
+        return Err(Error::InvalidFile(format!(
+            "unsupported format version {}",
+            version
+        )));
+    }
+
+    for &expected in spec_values(params).iter() {
+        if read_u32(input)? != expected {
+            return Err(Error::InvalidParameters(
+                "the database was saved with different parameters".to_string(),
+            ));
+        }
+    }
+
+    let num_columns = read_u32(input)?;
+    let num_plaintexts = read_u64(input)?;
+    let coeff_count = read_u64(input)?;
+
+    // every database is padded to the product of the dimension sizes, which replies
+    // require, and each NTT-form plaintext has one coefficient per coefficient of the
+    // polynomial modulus
+    let padded: u64 = params.nvec().iter().product();
+    if num_columns != params.num_columns()
+        || num_plaintexts != padded
+        || coeff_count != params.poly_degree() as u64
+    {
+        return Err(Error::InvalidFile(
+            "database shape does not match the parameters".to_string(),
+        ));
+    }
+
+    // slots at or above `used` are free without being listed, and the last used slot is
+    // never free: `delete` keeps `used` at the end of the slots in use
+    let epoch = read_u32(input)?;
+    let used = read_u32(input)?;
+    let free_count = read_u32(input)?;
+    let occupancy = || {
+        Err(Error::InvalidFile(
+            "slot occupancy does not match the parameters".to_string(),
+        ))
+    };
+    if used > params.ele_num() || free_count >= used.max(1) {
+        return occupancy();
+    }
+    let mut free = BTreeSet::new();
+    let mut next = 0;
+    for _ in 0..free_count {
+        let slot = read_u32(input)?;
+        if slot < next || slot >= used - 1 {
+            return occupancy();
+        }
+        free.insert(slot);
+        next = slot + 1;
+    }
+
+    Ok(Header {
+        num_plaintexts,
+        coeff_count,
+        epoch,
+        used,
+        free,
+    })
+}
+
+fn spec_values(params: &PirParameters) -> [u32; 5] {
+    [
+        params.ele_num(),
+        params.ele_size(),
+        params.poly_degree(),
+        params.log_plain_mod(),
+        params.d(),
+    ]
+}
+
+// hashes everything read or written through it
+struct Checksummed<T> {
+    inner: T,
+    hasher: Hasher64,
+}
+
+impl<T> Checksummed<T> {
+    fn new(inner: T) -> Checksummed<T> {
+        Checksummed {
+            inner,
+            hasher: Hasher64::new(CHECKSUM_SEED),
+        }
+    }
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.write(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.write(&buf[..n]);
+        Ok(n)
+    }
+}
+
+// like Read::read_exact, but reports a truncated file as such
+fn read_exact<R: Read>(input: &mut R, buf: &mut [u8]) -> Result<()> {
+    input.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => Error::InvalidFile("file is truncated".to_string()),
+        _ => Error::from(e),
+    })
+}
+
+fn read_u32<R: Read>(input: &mut R) -> Result<u32> {
+    let mut bytes = [0u8; 4];
+    read_exact(input, &mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(input: &mut R) -> Result<u64> {
+    let mut bytes = [0u8; 8];
+    read_exact(input, &mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
@@ -0,0 +1,155 @@
+// Setting up the database from a stream of elements. Elements are packed into plaintexts
+// the way `set_database` packs them, and each plaintext is transformed to NTT form as soon
+// as it is full, so only one plaintext per column is buffered instead of the whole
+// collection.
+
+use libc::{self, c_char};
+use std::io::{self, Read};
+use std::ptr;
+
+use super::persist::new_preprocessed_db;
+use super::PirServer;
+use crate::{check, Error, Result};
+
+extern "C" {
+    pub(super) fn set_plaintext_elements(
+        pir_server: *mut libc::c_void,
+        params: *const libc::c_void,
+        index: u64,
+        bytes: *const u8,
+        size: u64,
+        error: &mut *mut c_char,
+    ) -> i32;
+}
+
+impl PirServer {
+    /// Like `setup_bytes`, but takes the `ele_num` elements one at a time; each must be
+    /// `ele_size` bytes long.
+    pub fn setup_from_iter<I>(&mut self, elements: I) -> Result<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let ele_num = self.ele_num as usize;
+        let mut elements = elements.into_iter();
+
+        self.ingest(|index, buf| match elements.next() {
+            Some(element) => {
+                let element = element.as_ref();
+                if element.len() != buf.len() {
+                    return Err(Error::SizeMismatch {
+                        expected: buf.len(),
+                        actual: element.len(),
+                    });
+                }
+                buf.copy_from_slice(element);
+                Ok(())
+            }
+            None => Err(Error::SizeMismatch {
+                expected: ele_num,
+                actual: index,
+            }),
+        })?;
+
+        // leftover elements mean the collection does not match the parameters
+        let extra = elements.count();
+        if extra > 0 {
+            self.is_set_up = false;
+            return Err(Error::SizeMismatch {
+                expected: ele_num,
+                actual: ele_num + extra,
+            });
+        }
+        Ok(())
+    }
+
+    /// Like `setup_bytes`, but reads the `ele_num * ele_size` bytes of the collection from
+    /// `reader`. Bytes after the collection are not read.
+    pub fn setup_from_reader<R: Read>(&mut self, mut reader: R) -> Result<()> {
+        let expected = self.ele_num as usize * self.ele_size as usize;
+
+        self.ingest(|index, buf| {
+            reader.read_exact(buf).map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => Error::SizeMismatch {
+                    expected,
+                    actual: index * buf.len(),
+                },
+                _ => Error::from(e),
+            })
+        })
+    }
+
+    // fills the database element by element: `next(i, buf)` writes element i into `buf`
+    pub(super) fn ingest<F>(&mut self, mut next: F) -> Result<()>
+    where
+        F: FnMut(usize, &mut [u8]) -> Result<()>,
+    {
+        // a failure below leaves a partially set database behind
+        self.is_set_up = false;
+        self.mapped = None;
+        self.used = self.ele_num;
+        self.free.clear();
+
+        // the database is padded to the product of the dimension sizes
+        let num_plaintexts: u64 = self.params.nvec().iter().product();
+        for &server in self.servers.iter() {
+            unsafe {
+                let mut error: *mut c_char = ptr::null_mut();
+                let status = new_preprocessed_db(server, num_plaintexts, &mut error);
+                check(status, error)?;
+            }
+        }
+
+        let ele_size = self.ele_size as usize;
+        let column_size = self.column_size as usize;
+        let per_plaintext = self.params.elements_per_plaintext() as usize;
+
+        let mut element = vec![0u8; ele_size];
+        let mut columns = vec![Vec::with_capacity(per_plaintext * column_size); self.servers.len()];
+        let mut plaintext: u64 = 0;
+
+        for index in 0..self.ele_num as usize {
+            next(index, &mut element)?;
+
+            // the last column is zero-padded
+            for (column, buf) in columns.iter_mut().enumerate() {
+                let start = column * column_size;
+                let end = (start + column_size).min(ele_size);
+                buf.extend_from_slice(&element[start..end]);
+                buf.resize(buf.len() + column_size - (end - start), 0);
+            }
+
+            if (index + 1) % per_plaintext == 0 || index + 1 == self.ele_num as usize {
+                for (&server, buf) in self.servers.iter().zip(columns.iter_mut()) {
+                    self.set_plaintext(server, plaintext, buf)?;
+                    buf.clear();
+                }
+                plaintext += 1;
+            }
+        }
+
+        for index in plaintext..num_plaintexts {
+            for &server in self.servers.iter() {
+                self.set_plaintext(server, index, &[])?;
+            }
+        }
+
+        self.is_set_up = true;
+        Ok(())
+    }
+
+    fn set_plaintext(&self, server: *mut libc::c_void, index: u64, bytes: &[u8]) -> Result<()> {
+        unsafe {
+            let mut error: *mut c_char = ptr::null_mut();
+            let status = set_plaintext_elements(
+                server,
+                self.params.as_ptr(),
+                index,
+                bytes.as_ptr(),
+                bytes.len() as u64,
+                &mut error,
+            );
+            check(status, error)
+        }
+    }
+}
@@ -0,0 +1,423 @@
+//! Databases of variable-length records.
+//!
+//! Each record is stored with a little-endian `u32` length prefix in a slot of its size
+//! class, padded with zeros. Every size class is served as its own PIR database whose
+//! slot size is that of its largest record, so short records do not have to be padded
+//! to the size of the largest record overall. The client queries every class on each
+//! lookup (dummy queries for the classes it does not need) so that the server does not
+//! learn the class, and thus the length, of the requested record.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::client::PirClient;
+use super::params::{PirParameters, POLY_DEGREE};
+use super::server::PirServer;
+use super::{ClientHandle, Error, PirQuery, PirReply, Result};
+
+/// Bytes of the length prefix stored in front of each record.
+pub const LENGTH_PREFIX_SIZE: usize = 4;
+
+/// Largest number of size classes, which are numbered with one byte.
+pub const MAX_SIZE_CLASSES: u32 = 256;
+
+/// Public parameters of a `VarLenDatabase`: the size classes and the class of every
+/// record.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct VarLenParameters {
+    num_records: u32,
+    // slot size of each class, length prefix included
+    slot_sizes: Vec<u32>,
+    // class of each record; empty when there is a single class
+    record_classes: Vec<u8>,
+    poly_degree: u32,
+    log_plain_mod: u32,
+    d: u32,
+}
+
+impl VarLenParameters {
+    pub fn num_records(&self) -> u32 {
+        self.num_records
+    }
+
+    pub fn num_classes(&self) -> u32 {
+        self.slot_sizes.len() as u32
+    }
+
+    /// Slot size of each class in bytes, length prefix included.
+    pub fn slot_sizes(&self) -> &[u32] {
+        &self.slot_sizes
+    }
+}
+
+// PIR parameters of every class and where each record is stored
+struct Layout {
+    params: Vec<PirParameters>,
+    // class and position of each record; empty when there is a single class
+    positions: Vec<(u32, u32)>,
+}
+
+impl Layout {
+    fn new(varlen: &VarLenParameters) -> Result<Layout> {
+        let invalid = |msg: &str| Err(Error::InvalidParameters(msg.to_string()));
+
+        if varlen.slot_sizes.is_empty() || varlen.slot_sizes.len() > MAX_SIZE_CLASSES as usize {
+            return invalid("the number of size classes must be between 1 and 256");
+        }
+        if varlen.slot_sizes.len() == 1 && !varlen.record_classes.is_empty() {
+            return invalid("record classes given for a single size class");
+        }
+        if varlen.slot_sizes.len() > 1 && varlen.record_classes.len() != varlen.num_records as usize
+        {
+            return invalid("every record must have a size class");
+        }
+
+        let mut class_sizes = vec![0u32; varlen.slot_sizes.len()];
+        let mut positions = Vec::with_capacity(varlen.record_classes.len());
+        for &class in varlen.record_classes.iter() {
+            let count = class_sizes
+                .get_mut(class as usize)
+                .ok_or_else(|| Error::InvalidParameters(format!("unknown size class {}", class)))?;
+            positions.push((class as u32, *count));
+            *count += 1;
+        }
+        if varlen.slot_sizes.len() == 1 {
+            class_sizes[0] = varlen.num_records;
+        }
+
+        let params = class_sizes
+            .iter()
+            .zip(varlen.slot_sizes.iter())
+            .map(|(&ele_num, &slot_size)| {
+                PirParameters::new(
+                    ele_num,
+                    slot_size,
+                    varlen.poly_degree,
+                    varlen.log_plain_mod,
+                    varlen.d,
+                )
+            })
+            .collect::<Result<Vec<PirParameters>>>()?;
+
+        Ok(Layout { params, positions })
+    }
+
+    // class and position of record `index`
+    fn locate(&self, index: u32) -> (u32, u32) {
+        if self.positions.is_empty() {
+            (0, index)
+        } else {
+            self.positions[index as usize]
+        }
+    }
+}
+
+/// Records padded into the slots of their size classes, ready to be served by a
+/// `VarLenPirServer`.
+pub struct VarLenDatabase {
+    params: VarLenParameters,
+    // slots of each class
+    classes: Vec<Vec<u8>>,
+}
+
+impl VarLenDatabase {
+    pub fn builder() -> VarLenDatabaseBuilder {
+        VarLenDatabaseBuilder::default()
+    }
+
+    pub fn params(&self) -> &VarLenParameters {
+        &self.params
+    }
+}
+
+/// Builds a `VarLenDatabase`. The records are required; by default they are stored in a
+/// single size class with N = 2048, log t = 12 and d = 2.
+#[derive(Clone, Debug)]
+pub struct VarLenDatabaseBuilder {
+    records: Vec<Vec<u8>>,
+    size_classes: u32,
+    poly_degree: u32,
+    log_plain_mod: u32,
+    d: u32,
+}
+
+impl Default for VarLenDatabaseBuilder {
+    fn default() -> VarLenDatabaseBuilder {
+        VarLenDatabaseBuilder {
+            records: Vec::new(),
+            size_classes: 1,
+            poly_degree: POLY_DEGREE,
+            log_plain_mod: 12,
+            d: 2,
+        }
+    }
+}
+
+impl VarLenDatabaseBuilder {
+    pub fn records(mut self, records: Vec<Vec<u8>>) -> Self {
+        self.records = records;
+        self
+    }
+
+    pub fn push(mut self, record: Vec<u8>) -> Self {
+        self.records.push(record);
+        self
+    }
+
+    /// Maximum number of size classes. Records are split into classes holding about the
+    /// same number of records, from shortest to longest; fewer classes are used if
+    /// several would have the same slot size.
+    pub fn size_classes(mut self, size_classes: u32) -> Self {
+        self.size_classes = size_classes;
+        self
+    }
+
+    pub fn poly_degree(mut self, poly_degree: u32) -> Self {
+        self.poly_degree = poly_degree;
+        self
+    }
+
+    pub fn log_plain_mod(mut self, log_plain_mod: u32) -> Self {
+        self.log_plain_mod = log_plain_mod;
+        self
+    }
+
+    pub fn d(mut self, d: u32) -> Self {
+        self.d = d;
+        self
+    }
+
+    pub fn build(&self) -> Result<VarLenDatabase> {
+        if self.records.is_empty() {
+            return Err(Error::InvalidParameters("no records".to_string()));
+        }
+        if self.size_classes == 0 || self.size_classes > MAX_SIZE_CLASSES {
+            return Err(Error::InvalidParameters(format!(
+                "size_classes must be between 1 and {}",
+                MAX_SIZE_CLASSES
+            )));
+        }
+        if let Some(record) = self
+            .records
+            .iter()
+            .find(|r| r.len() > (u32::MAX as usize - LENGTH_PREFIX_SIZE))
+        {
+            return Err(Error::SizeMismatch {
+                expected: u32::MAX as usize - LENGTH_PREFIX_SIZE,
+                actual: record.len(),
+            });
+        }
+
+        let slot_sizes = self.slot_sizes();
+        let record_classes: Vec<u8> = if slot_sizes.len() == 1 {
+            Vec::new()
+        } else {
+            self.records
+                .iter()
+                .map(|r| {
+                    let slot = (r.len() + LENGTH_PREFIX_SIZE) as u32;
+                    // slot sizes are sorted and the last one fits every record
+                    slot_sizes.iter().position(|&s| s >= slot).unwrap_or(0) as u8
+                })
+                .collect()
+        };
+
+        let params = VarLenParameters {
+            num_records: self.records.len() as u32,
+            slot_sizes,
+            record_classes,
+            poly_degree: self.poly_degree,
+            log_plain_mod: self.log_plain_mod,
+            d: self.d,
+        };
+        let layout = Layout::new(&params)?;
+
+        let mut classes: Vec<Vec<u8>> = layout
+            .params
+            .iter()
+            .map(|p| vec![0u8; p.ele_num() as usize * p.ele_size() as usize])
+            .collect();
+        for (index, record) in self.records.iter().enumerate() {
+            let (class, pos) = layout.locate(index as u32);
+            let slot_size = params.slot_sizes[class as usize] as usize;
+            let start = pos as usize * slot_size;
+            let slot = &mut classes[class as usize][start..start + slot_size];
+            slot[..LENGTH_PREFIX_SIZE].copy_from_slice(&(record.len() as u32).to_le_bytes());
+            slot[LENGTH_PREFIX_SIZE..LENGTH_PREFIX_SIZE + record.len()].copy_from_slice(record);
+        }
+
+        Ok(VarLenDatabase { params, classes })
+    }
+
+    // slot sizes of equally populated classes of records sorted by length
+    fn slot_sizes(&self) -> Vec<u32> {
+        let mut lengths: Vec<u32> = self
+            .records
+            .iter()
+            .map(|r| (r.len() + LENGTH_PREFIX_SIZE) as u32)
+            .collect();
+        lengths.sort_unstable();
+
+        let classes = (self.size_classes as usize).min(lengths.len());
+        let mut slot_sizes: Vec<u32> = (1..=classes)
+            .map(|c| lengths[(c * lengths.len()).div_ceil(classes) - 1])
+            .collect();
+        slot_sizes.dedup();
+        slot_sizes
+    }
+}
+
+pub struct VarLenPirServer {
+    params: VarLenParameters,
+    servers: Vec<PirServer>,
+}
+
+impl VarLenPirServer {
+    pub fn new(db: &VarLenDatabase) -> Result<VarLenPirServer> {
+        let layout = Layout::new(&db.params)?;
+
+        let mut servers = Vec::with_capacity(layout.params.len());
+        for (params, slots) in layout.params.iter().zip(db.classes.iter()) {
+            let mut server = PirServer::new(params)?;
+            server.setup_bytes(slots, params.ele_size() as usize)?;
+            servers.push(server);
+        }
+
+        Ok(VarLenPirServer {
+            params: db.params.clone(),
+            servers,
+        })
+    }
+
+    pub fn params(&self) -> &VarLenParameters {
+        &self.params
+    }
+
+    /// Registers the key of a `VarLenPirClient`, once for all size classes: it is kept by
+    /// the first class, whose copy every class replies with.
+    pub fn set_galois_key(&mut self, key: &[u8]) -> Result<ClientHandle> {
+        self.servers[0].set_galois_key(key)
+    }
+
+    /// Bytes of Galois keys held in memory by every size class.
+    pub fn key_memory_usage(&self) -> usize {
+        self.servers.iter().map(PirServer::key_memory_usage).sum()
+    }
+
+    /// Answers one query per size class, in class order, with the handle returned by
+    /// `set_galois_key`.
+    pub fn gen_replies(&self, queries: &[PirQuery], client: ClientHandle) -> Result<Vec<PirReply>> {
+        if queries.len() != self.servers.len() {
+            return Err(Error::SizeMismatch {
+                expected: self.servers.len(),
+                actual: queries.len(),
+            });
+        }
+
+        self.servers
+            .iter()
+            .zip(queries.iter())
+            .map(|(server, query)| server.gen_reply_with_keys(query, client, &self.servers[0]))
+            .collect()
+    }
+}
+
+pub struct VarLenPirClient {
+    params: VarLenParameters,
+    layout: Layout,
+    // one per size class, since each class has its own database layout; they share the
+    // secret key of the first, so that servers need a single Galois key
+    clients: Vec<PirClient>,
+}
+
+impl VarLenPirClient {
+    pub fn new(params: &VarLenParameters) -> Result<VarLenPirClient> {
+        let layout = Layout::new(params)?;
+        let first = PirClient::new(&layout.params[0])?;
+        let state = first.export_secret_state()?;
+
+        let mut clients = Vec::with_capacity(layout.params.len());
+        clients.push(first);
+        for class_params in layout.params[1..].iter() {
+            clients.push(PirClient::from_secret_state(class_params, &state)?);
+        }
+
+        Ok(VarLenPirClient {
+            params: params.clone(),
+            layout,
+            clients,
+        })
+    }
+
+    pub fn params(&self) -> &VarLenParameters {
+        &self.params
+    }
+
+    /// The Galois key every size class is queried with.
+    pub fn get_key(&self) -> &Vec<u8> {
+        self.clients[0].get_key()
+    }
+
+    /// The handle `VarLenPirServer::set_galois_key` returns for the key of this client.
+    pub fn handle(&self) -> ClientHandle {
+        self.clients[0].handle()
+    }
+
+    /// Generates one query per size class retrieving record `index`.
+    pub fn gen_queries(&self, index: u32) -> Result<Vec<PirQuery>> {
+        self.check_index(index)?;
+        let (class, pos) = self.layout.locate(index);
+
+        let mut rng = rand::thread_rng();
+        self.clients
+            .iter()
+            .enumerate()
+            .map(|(c, client)| {
+                if c as u32 == class {
+                    client.gen_query(pos)
+                } else {
+                    client.gen_query(rng.gen_range(0..client.params().ele_num()))
+                }
+            })
+            .collect()
+    }
+
+    /// Decodes the replies to `gen_queries(index)` and returns the record without its
+    /// padding.
+    pub fn decode_replies(&self, index: u32, replies: &[PirReply]) -> Result<Vec<u8>> {
+        self.check_index(index)?;
+        if replies.len() != self.clients.len() {
+            return Err(Error::SizeMismatch {
+                expected: self.clients.len(),
+                actual: replies.len(),
+            });
+        }
+
+        let (class, pos) = self.layout.locate(index);
+        let slot =
+            self.clients[class as usize].decode_reply_to_vec(pos, &replies[class as usize])?;
+
+        let mut prefix = [0u8; LENGTH_PREFIX_SIZE];
+        prefix.copy_from_slice(&slot[..LENGTH_PREFIX_SIZE]);
+        let len = u32::from_le_bytes(prefix) as usize;
+        if len > slot.len() - LENGTH_PREFIX_SIZE {
+            return Err(Error::MalformedCiphertext(format!(
+                "record length {} exceeds the slot size {}",
+                len,
+                slot.len()
+            )));
+        }
+
+        Ok(slot[LENGTH_PREFIX_SIZE..LENGTH_PREFIX_SIZE + len].to_vec())
+    }
+
+    fn check_index(&self, index: u32) -> Result<()> {
+        if index >= self.params.num_records {
+            return Err(Error::IndexOutOfRange {
+                index: index as usize,
+                len: self.params.num_records as usize,
+            });
+        }
+        Ok(())
+    }
+}
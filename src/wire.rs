@@ -0,0 +1,145 @@
+// Framing queries and replies for the network. The header identifies what the payload is
+// and who it is for, so that it can be checked before any ciphertext is parsed.
+//
+// Layout, all integers little-endian:
+//   magic (8 bytes), format version (u32)
+//   parameter fingerprint (u64), client handle (u64)
+//   ciphertext count (u32): `num` of the query or reply
+//   payload length (u64), payload: the ciphertexts, in full or seeded form
+
+use crate::params::{PirParameters, CIPHERTEXT_SIZE};
+use crate::{seeded, ClientHandle, Error, PirQuery, PirReply, Result};
+
+const QUERY_MAGIC: [u8; 8] = *b"SEALPIRQ";
+const REPLY_MAGIC: [u8; 8] = *b"SEALPIRR";
+
+// bumped whenever the layout above changes
+const FORMAT_VERSION: u32 = 1;
+
+const HEADER_SIZE: usize = 8 + 4 + 8 + 8 + 4 + 8;
+
+impl PirQuery {
+    /// Frames the query of `client` for a server with parameters `params`.
+    pub fn to_bytes(&self, params: &PirParameters, client: ClientHandle) -> Vec<u8> {
+        frame(&QUERY_MAGIC, params, client, self.num, &self.query)
+    }
+
+    /// Parses a query framed by `to_bytes` and returns it with the handle of its client.
+    /// Fails with `InvalidParameters` if it was built for other parameters, e.g., those of
+    /// an earlier epoch.
+    pub fn from_bytes(bytes: &[u8], params: &PirParameters) -> Result<(PirQuery, ClientHandle)> {
+        let (client, num, payload) = unframe(&QUERY_MAGIC, "query", params, bytes)?;
+
+        // a query carries `num` ciphertexts for each of the d dimensions; the size of a
+        // seeded one is checked when it is expanded
+        let expected = num as u64 * params.d() as u64 * CIPHERTEXT_SIZE as u64;
+        if num == 0 || (!seeded::is_seeded(payload) && payload.len() as u64 != expected) {
+            return Err(Error::MalformedCiphertext(format!(
+                "{} bytes do not hold {} ciphertexts per dimension",
+                payload.len(),
+                num
+            )));
+        }
+
+        let query = PirQuery {
+            query: payload.to_vec(),
+            num,
+        };
+        Ok((query, client))
+    }
+}
+
+impl PirReply {
+    /// Frames the reply to `client`'s query with the server's parameters `params`.
+    pub fn to_bytes(&self, params: &PirParameters, client: ClientHandle) -> Vec<u8> {
+        frame(&REPLY_MAGIC, params, client, self.num, &self.reply)
+    }
+
+    /// Parses a reply framed by `to_bytes` and returns it with the handle of the client
+    /// it is for. Fails with `InvalidParameters` if the server has other parameters.
+    pub fn from_bytes(bytes: &[u8], params: &PirParameters) -> Result<(PirReply, ClientHandle)> {
+        let (client, num, payload) = unframe(&REPLY_MAGIC, "reply", params, bytes)?;
+
+        // every column replies with the same number of ciphertexts
+        if num == 0
+            || !num.is_multiple_of(params.num_columns())
+            || payload.len() as u64 != num as u64 * CIPHERTEXT_SIZE as u64
+        {
+            return Err(Error::MalformedCiphertext(format!(
+                "{} bytes do not hold a reply of {} ciphertexts",
+                payload.len(),
+                num
+            )));
+        }
+
+        let reply = PirReply {
+            reply: payload.to_vec(),
+            num,
+        };
+        Ok((reply, client))
+    }
+}
+
+fn frame(
+    magic: &[u8; 8],
+    params: &PirParameters,
+    client: ClientHandle,
+    num: u32,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + payload.len());
+    bytes.extend_from_slice(magic);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&params.fingerprint().to_le_bytes());
+    bytes.extend_from_slice(&client.0.to_le_bytes());
+    bytes.extend_from_slice(&num.to_le_bytes());
+    bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+// validates the header of a frame and returns the client handle, the ciphertext count and
+// the payload
+fn unframe<'a>(
+    magic: &[u8; 8],
+    what: &str,
+    params: &PirParameters,
+    bytes: &'a [u8],
+) -> Result<(ClientHandle, u32, &'a [u8])> {
+    let malformed = |msg: String| Err(Error::MalformedCiphertext(msg));
+
+    if bytes.len() < HEADER_SIZE {
+        return malformed(format!("{} is truncated", what));
+    }
+    let u32_at = |at: usize| {
+        let mut le = [0u8; 4];
+        le.copy_from_slice(&bytes[at..at + 4]);
+        u32::from_le_bytes(le)
+    };
+    let u64_at = |at: usize| {
+        let mut le = [0u8; 8];
+        le.copy_from_slice(&bytes[at..at + 8]);
+        u64::from_le_bytes(le)
+    };
+
+    if bytes[..8] != magic[..] {
+        return malformed(format!("not a framed {}", what));
+    }
+    if u32_at(8) != FORMAT_VERSION {
+        return malformed(format!("unsupported format version {}", u32_at(8)));
+    }
+    if u64_at(12) != params.fingerprint() {
+        return Err(Error::InvalidParameters(format!(
+            "the {} was built for different parameters",
+            what
+        )));
+    }
+
+    let client = ClientHandle(u64_at(20));
+    let num = u32_at(28);
+    let payload = &bytes[HEADER_SIZE..];
+    if u64_at(32) != payload.len() as u64 {
+        return malformed(format!("{} length does not match the frame size", what));
+    }
+    Ok((client, num, payload))
+}
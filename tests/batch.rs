@@ -0,0 +1,71 @@
+use rand::{Rng, RngCore};
+use sealpir::batch::{BatchParameters, BatchPirClient, BatchPirServer};
+use sealpir::Error;
+
+#[test]
+fn batch_pir_test() {
+    let num = 1000;
+    let batch_size = 16;
+
+    let mut collection: Vec<[u8; 288]> = Vec::new();
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..num {
+        let mut x: [u8; 288] = [0; 288];
+        rng.fill_bytes(&mut x);
+        collection.push(x);
+    }
+
+    let params = BatchParameters::new(num, 288, batch_size, 2048, 12, 2);
+
+    // the client only needs the public parameters, including the hash seed
+    let copy: BatchParameters =
+        bincode::deserialize(&bincode::serialize(&params).unwrap()).unwrap();
+
+    let mut server = BatchPirServer::new(&params).unwrap();
+    let client = BatchPirClient::new(&copy).unwrap();
+
+    server.set_galois_key(client.get_key()).unwrap();
+    server.setup(&collection[..]).unwrap();
+
+    let mut indices: Vec<u32> = (0..batch_size - 1)
+        .map(|_| rng.gen::<u32>() % num)
+        .collect();
+    indices.push(indices[0]); // duplicates are answered too
+
+    let (queries, assignment) = client.gen_queries(&indices).unwrap();
+    assert_eq!(queries.len(), params.num_buckets() as usize);
+
+    let replies = server.gen_replies(&queries, client.handle()).unwrap();
+    let results = client.decode_replies(&assignment, &replies).unwrap();
+
+    assert_eq!(results.len(), indices.len());
+    for (index, result) in indices.iter().zip(results.iter()) {
+        assert_eq!(&result[..], &collection[*index as usize][..]);
+    }
+}
+
+#[test]
+fn batch_pir_invalid_input_test() {
+    let num = 100;
+    let params = BatchParameters::new(num, 288, 4, 2048, 12, 2).with_seed(7);
+
+    let server = BatchPirServer::new(&params).unwrap();
+    let client = BatchPirClient::new(&params).unwrap();
+
+    assert_eq!(
+        client.gen_queries(&[0, 1, 2, 3, 4]).err(),
+        Some(Error::SizeMismatch {
+            expected: 4,
+            actual: 5
+        })
+    );
+    assert!(matches!(
+        client.gen_queries(&[num]),
+        Err(Error::IndexOutOfRange { .. })
+    ));
+
+    let (queries, _) = client.gen_queries(&[0]).unwrap();
+    assert!(server.gen_replies(&queries[1..], client.handle()).is_err());
+    assert!(BatchPirServer::new(&BatchParameters::new(num, 288, 0, 2048, 12, 2)).is_err());
+}
@@ -0,0 +1,126 @@
+use rand::RngCore;
+use sealpir::client::PirClient;
+use sealpir::params::PirParameters;
+use sealpir::server::PirServer;
+use sealpir::{Error, PirQuery};
+
+fn retrieve(server: &PirServer, client: &PirClient, index: usize) -> Vec<u8> {
+    let query = client.gen_query(index as u32).unwrap();
+    let reply = server.gen_reply(&query, client.handle()).unwrap();
+    client.decode_reply_to_vec(index as u32, &reply).unwrap()
+}
+
+#[test]
+fn capacity_append_delete_test() {
+    let capacity = 20;
+    let ele_size = 288;
+
+    let params = PirParameters::new(capacity, ele_size as u32, 2048, 12, 2).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    let client = PirClient::new(&params).unwrap();
+    server.set_galois_key(client.get_key()).unwrap();
+    server.setup_empty().unwrap();
+    assert!(server.is_empty());
+    assert_eq!(server.capacity(), capacity as usize);
+
+    let mut rng = rand::thread_rng();
+    let mut elements = Vec::new();
+    for i in 0..capacity as usize {
+        let mut element = vec![0u8; ele_size];
+        rng.fill_bytes(&mut element);
+        assert_eq!(server.append(&element).unwrap(), i);
+        elements.push(element);
+    }
+    assert!(server.is_full());
+    assert_eq!(
+        server.append(&elements[0]).err(),
+        Some(Error::CapacityExceeded {
+            capacity: capacity as usize
+        })
+    );
+
+    server.delete(3).unwrap();
+    server.delete(3).unwrap();
+    server.delete(12).unwrap();
+    assert_eq!(server.len(), capacity as usize - 2);
+    assert_eq!(retrieve(&server, &client, 3), vec![0u8; ele_size]);
+    assert_eq!(retrieve(&server, &client, 4), elements[4]);
+
+    // deleted slots are reused lowest first
+    assert_eq!(server.append(&elements[0]).unwrap(), 3);
+    assert_eq!(server.append(&elements[1]).unwrap(), 12);
+    assert_eq!(retrieve(&server, &client, 12), elements[1]);
+    assert!(server.is_full());
+
+    assert_eq!(
+        server.delete(capacity as usize).err(),
+        Some(Error::IndexOutOfRange {
+            index: capacity as usize,
+            len: capacity as usize
+        })
+    );
+}
+
+#[test]
+fn capacity_resize_test() {
+    let capacity = 20;
+    let ele_size = 5000; // two columns
+
+    let params = PirParameters::new(capacity, ele_size as u32, 2048, 12, 2).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    let old_client = PirClient::new(&params).unwrap();
+    server.set_galois_key(old_client.get_key()).unwrap();
+    server.setup_empty().unwrap();
+
+    let mut rng = rand::thread_rng();
+    let mut elements = Vec::new();
+    for _ in 0..capacity {
+        let mut element = vec![0u8; ele_size];
+        rng.fill_bytes(&mut element);
+        server.append(&element).unwrap();
+        elements.push(element);
+    }
+
+    server.resize(500).unwrap();
+    assert_eq!(server.capacity(), 500);
+    assert_eq!(server.params().ele_num(), 500);
+    assert_eq!(server.params().epoch(), params.epoch() + 1);
+    assert_ne!(old_client.params(), server.params());
+
+    // queries of the old epoch are rejected once framed
+    let query = old_client.gen_query(0).unwrap();
+    let framed = query.to_bytes(old_client.params(), old_client.handle());
+    assert!(matches!(
+        PirQuery::from_bytes(&framed, server.params()),
+        Err(Error::InvalidParameters(_))
+    ));
+
+    // registered keys are kept: the old client, rebuilt with the new parameters from its
+    // secret state, needs no new key
+    let state = old_client.export_secret_state().unwrap();
+    let client = PirClient::from_secret_state(server.params(), &state).unwrap();
+    assert_eq!(client.handle(), old_client.handle());
+    for &index in [0, 7, capacity as usize - 1].iter() {
+        assert_eq!(retrieve(&server, &client, index), elements[index]);
+    }
+
+    let mut element = vec![0u8; ele_size];
+    rng.fill_bytes(&mut element);
+    assert_eq!(server.append(&element).unwrap(), capacity as usize);
+    assert_eq!(retrieve(&server, &client, capacity as usize), element);
+
+    // trailing free slots do not count against a smaller capacity
+    server.delete(capacity as usize).unwrap();
+    server.delete(capacity as usize - 1).unwrap();
+    assert!(matches!(
+        server.resize(capacity - 2),
+        Err(Error::InvalidParameters(_))
+    ));
+    server.resize(capacity - 1).unwrap();
+    assert!(server.is_full());
+    assert_eq!(server.params().epoch(), params.epoch() + 2);
+
+    let client = PirClient::new(server.params()).unwrap();
+    server.set_galois_key(client.get_key()).unwrap();
+    assert_eq!(retrieve(&server, &client, 5), elements[5]);
+}
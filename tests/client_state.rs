@@ -0,0 +1,66 @@
+use rand::RngCore;
+use sealpir::client::PirClient;
+use sealpir::params::PirParameters;
+use sealpir::server::PirServer;
+use sealpir::Error;
+
+#[test]
+fn client_state_restore_test() {
+    let ele_num = 100;
+    let ele_size = 288;
+    let params = PirParameters::new(ele_num, ele_size, 2048, 12, 2).unwrap();
+
+    let mut collection = vec![0u8; ele_num as usize * ele_size as usize];
+    rand::thread_rng().fill_bytes(&mut collection);
+    let mut server = PirServer::new(&params).unwrap();
+    server.setup_bytes(&collection, ele_size as usize).unwrap();
+
+    let client = PirClient::new(&params).unwrap();
+    let handle = server.set_galois_key(client.get_key()).unwrap();
+    let state = client.export_secret_state().unwrap();
+    drop(client);
+
+    // the restored client uses the key the server already has
+    let client = PirClient::from_secret_state(&params, &state).unwrap();
+    assert_eq!(client.handle(), handle);
+    let index = 17;
+    let query = client.gen_query(index).unwrap();
+    let reply = server.gen_reply(&query, handle).unwrap();
+    let result = client.decode_reply_to_vec(index, &reply).unwrap();
+    let start = index as usize * ele_size as usize;
+    assert_eq!(&result[..], &collection[start..start + ele_size as usize]);
+
+    // states survive a resize
+    server.resize(2 * ele_num).unwrap();
+    let client = PirClient::from_secret_state(server.params(), &state).unwrap();
+    let query = client.gen_query(index).unwrap();
+    let reply = server.gen_reply(&query, handle).unwrap();
+    let result = client.decode_reply_to_vec(index, &reply).unwrap();
+    assert_eq!(&result[..], &collection[start..start + ele_size as usize]);
+}
+
+#[test]
+fn client_state_invalid_test() {
+    let params = PirParameters::new(100, 288, 2048, 12, 2).unwrap();
+    let state = PirClient::new(&params)
+        .unwrap()
+        .export_secret_state()
+        .unwrap();
+
+    let mut corrupted = state.to_vec();
+    corrupted[100] ^= 1;
+    assert!(matches!(
+        PirClient::from_secret_state(&params, &corrupted),
+        Err(Error::InvalidFile(_))
+    ));
+    assert!(matches!(
+        PirClient::from_secret_state(&params, &state[..state.len() / 2]),
+        Err(Error::InvalidFile(_))
+    ));
+
+    let other = PirParameters::new(100, 288, 2048, 20, 2).unwrap();
+    assert!(matches!(
+        PirClient::from_secret_state(&other, &state),
+        Err(Error::InvalidParameters(_))
+    ));
+}
@@ -0,0 +1,260 @@
+use rand::{Rng, RngCore};
+use sealpir::client::PirClient;
+use sealpir::params::PirParameters;
+use sealpir::server::PirServer;
+use sealpir::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+fn temp_path(name: &str) -> PathBuf {
+    let mut path = std::env::temp_dir();
+    path.push(format!(
+        "sealpir-{}-{}-{}",
+        name,
+        std::process::id(),
+        rand::thread_rng().gen::<u64>()
+    ));
+    path
+}
+
+fn random_collection(params: &PirParameters) -> Vec<u8> {
+    let mut collection = vec![0u8; params.ele_num() as usize * params.ele_size() as usize];
+    rand::thread_rng().fill_bytes(&mut collection);
+    collection
+}
+
+fn setup(params: &PirParameters, collection: &[u8]) -> PirServer {
+    let mut server = PirServer::new(params).unwrap();
+    server
+        .setup_bytes(collection, params.ele_size() as usize)
+        .unwrap();
+    server
+}
+
+fn clients(params: &PirParameters, count: usize) -> Vec<PirClient> {
+    (0..count)
+        .map(|_| PirClient::new(params).unwrap())
+        .collect()
+}
+
+#[test]
+fn keys_remove_test() {
+    let params = PirParameters::new(100, 288, 2048, 12, 2).unwrap();
+    let mut server = setup(&params, &random_collection(&params));
+    let clients = clients(&params, 3);
+
+    for client in clients.iter() {
+        server.set_galois_key(client.get_key()).unwrap();
+    }
+    // registering a key again does not add a client
+    let handle = server.set_galois_key(clients[1].get_key()).unwrap();
+    assert_eq!(handle, clients[1].handle());
+    assert!(server.has_client(handle));
+    assert_eq!(server.client_count(), 3);
+    assert_eq!(server.key_memory_usage(), 3 * clients[0].get_key().len());
+
+    assert!(server.remove_galois_key(handle).unwrap());
+    assert!(!server.remove_galois_key(handle).unwrap());
+    assert!(!server.has_client(handle));
+    assert_eq!(server.client_count(), 2);
+
+    let query = clients[1].gen_query(0).unwrap();
+    assert_eq!(
+        server.gen_reply(&query, handle).err(),
+        Some(Error::UnknownClient(handle))
+    );
+    let query = clients[2].gen_query(0).unwrap();
+    assert!(server.gen_reply(&query, clients[2].handle()).is_ok());
+}
+
+#[test]
+fn keys_eviction_test() {
+    let params = PirParameters::new(100, 288, 2048, 12, 2).unwrap();
+    let mut server = setup(&params, &random_collection(&params));
+    let clients = clients(&params, 5);
+    let handles: Vec<_> = clients.iter().map(PirClient::handle).collect();
+    let key_size = clients[0].get_key().len();
+
+    // room for two keys; client 0 is used after client 1 registers
+    server.set_key_memory_budget(Some(2 * key_size)).unwrap();
+    server.set_galois_key(clients[0].get_key()).unwrap();
+    thread::sleep(Duration::from_millis(5));
+    server.set_galois_key(clients[1].get_key()).unwrap();
+    thread::sleep(Duration::from_millis(5));
+    let query = clients[0].gen_query(0).unwrap();
+    server.gen_reply(&query, handles[0]).unwrap();
+    thread::sleep(Duration::from_millis(5));
+    server.set_galois_key(clients[2].get_key()).unwrap();
+
+    assert!(server.has_client(handles[0]));
+    assert!(!server.has_client(handles[1]));
+    assert!(server.has_client(handles[2]));
+    assert_eq!(server.key_memory_usage(), 2 * key_size);
+
+    // a key larger than the budget is still registered
+    server.set_key_memory_budget(Some(key_size / 2)).unwrap();
+    assert_eq!(server.client_count(), 0);
+    server.set_galois_key(clients[3].get_key()).unwrap();
+    let query = clients[3].gen_query(0).unwrap();
+    assert!(server.gen_reply(&query, handles[3]).is_ok());
+
+    server.set_key_memory_budget(None).unwrap();
+    server.set_key_ttl(Some(Duration::from_millis(50))).unwrap();
+    server.set_galois_key(clients[4].get_key()).unwrap();
+    assert_eq!(server.client_count(), 2);
+    thread::sleep(Duration::from_millis(100));
+    server.evict_expired_keys().unwrap();
+    assert_eq!(server.client_count(), 0);
+}
+
+#[test]
+fn keys_store_test() {
+    let params = PirParameters::new(100, 5000, 2048, 12, 2).unwrap();
+    let collection = random_collection(&params);
+    let mut server = setup(&params, &collection);
+    let client = PirClient::new(&params).unwrap();
+    let dir = temp_path("keys");
+
+    let other = PirClient::new(&params).unwrap();
+    let handle = client.handle();
+
+    server.open_key_store(&dir).unwrap();
+    server.set_galois_key(client.get_key()).unwrap();
+    server.set_galois_key(other.get_key()).unwrap();
+
+    // evicted keys stay in the store
+    server.set_key_memory_budget(Some(0)).unwrap();
+    assert_eq!(server.key_memory_usage(), 0);
+    assert!(server.has_client(handle));
+    assert!(server.has_client(other.handle()));
+    assert!(server.remove_galois_key(other.handle()).unwrap());
+    drop(server);
+
+    // a restarted server finds the keys, but has to load them before replying
+    let mut server = setup(&params, &collection);
+    server.open_key_store(&dir).unwrap();
+    assert!(server.has_client(handle));
+    assert!(!server.has_client(other.handle()));
+    assert_eq!(server.client_count(), 1);
+
+    let index = 42;
+    let query = client.gen_query(index).unwrap();
+    assert_eq!(
+        server.gen_reply(&query, handle).err(),
+        Some(Error::KeyNotLoaded(handle))
+    );
+    assert!(server.load_galois_key(handle).unwrap());
+    assert!(!server.load_galois_key(other.handle()).unwrap());
+
+    let reply = server.gen_reply(&query, handle).unwrap();
+    let result = client.decode_reply_to_vec(index, &reply).unwrap();
+    let start = index as usize * 5000;
+    assert_eq!(&result[..], &collection[start..start + 5000]);
+
+    // corrupted key files are rejected
+    let path = dir.join(format!("{}.key", handle));
+    let mut file = fs::read(&path).unwrap();
+    file[100] ^= 1;
+    server.remove_galois_key(handle).unwrap();
+    fs::write(&path, &file).unwrap();
+    server.open_key_store(&dir).unwrap();
+    assert!(matches!(
+        server.load_galois_key(handle),
+        Err(Error::InvalidFile(_))
+    ));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn keys_load_all_test() {
+    let params = PirParameters::new(100, 288, 2048, 12, 2).unwrap();
+    let collection = random_collection(&params);
+    let mut server = setup(&params, &collection);
+    let clients = clients(&params, 3);
+    let dir = temp_path("load-all");
+
+    server.open_key_store(&dir).unwrap();
+    for client in clients.iter() {
+        server.set_galois_key(client.get_key()).unwrap();
+    }
+    drop(server);
+
+    // a restarted server loads the stored keys before it is shared between threads
+    let mut server = setup(&params, &collection);
+    server.open_key_store(&dir).unwrap();
+    assert_eq!(server.load_all_galois_keys().unwrap(), 3);
+    assert_eq!(server.load_all_galois_keys().unwrap(), 0);
+    assert_eq!(server.key_memory_usage(), 3 * clients[0].get_key().len());
+
+    let server = Arc::new(server);
+    let handles: Vec<_> = clients
+        .iter()
+        .enumerate()
+        .map(|(index, client)| {
+            let query = client.gen_query(index as u32).unwrap();
+            let server = Arc::clone(&server);
+            let client_handle = client.handle();
+            thread::spawn(move || server.gen_reply(&query, client_handle).unwrap())
+        })
+        .collect();
+
+    for (index, (client, handle)) in clients.iter().zip(handles).enumerate() {
+        let reply = handle.join().unwrap();
+        let result = client.decode_reply_to_vec(index as u32, &reply).unwrap();
+        let start = index * 288;
+        assert_eq!(&result[..], &collection[start..start + 288]);
+    }
+
+    // with a memory budget, only the keys that fit are loaded
+    let mut server = setup(&params, &collection);
+    server
+        .set_key_memory_budget(Some(2 * clients[0].get_key().len()))
+        .unwrap();
+    server.open_key_store(&dir).unwrap();
+    assert_eq!(server.load_all_galois_keys().unwrap(), 2);
+    assert_eq!(server.client_count(), 3);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn keys_conflict_test() {
+    let params = PirParameters::new(100, 288, 2048, 12, 2).unwrap();
+    let collection = random_collection(&params);
+    let mut server = setup(&params, &collection);
+    let clients = clients(&params, 2);
+    let (victim, other) = (clients[0].handle(), clients[1].handle());
+    let dir = temp_path("conflict");
+
+    server.open_key_store(&dir).unwrap();
+    server.set_galois_key(clients[0].get_key()).unwrap();
+    server.set_galois_key(clients[1].get_key()).unwrap();
+    drop(server);
+
+    // stands for a colliding key: the key of the other client stored under the handle
+    // of the victim
+    fs::copy(
+        dir.join(format!("{}.key", other)),
+        dir.join(format!("{}.key", victim)),
+    )
+    .unwrap();
+    let mut server = setup(&params, &collection);
+    server.open_key_store(&dir).unwrap();
+    assert_eq!(
+        server.set_galois_key(clients[0].get_key()).err(),
+        Some(Error::KeyConflict(victim))
+    );
+
+    // registering the key that is stored is not a conflict
+    assert_eq!(server.set_galois_key(clients[1].get_key()).unwrap(), other);
+    let query = clients[1].gen_query(7).unwrap();
+    let reply = server.gen_reply(&query, other).unwrap();
+    let result = clients[1].decode_reply_to_vec(7, &reply).unwrap();
+    assert_eq!(&result[..], &collection[7 * 288..8 * 288]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
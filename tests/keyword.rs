@@ -0,0 +1,67 @@
+use rand::RngCore;
+use sealpir::keyword::{KeywordParameters, KeywordPirClient, KeywordPirServer, NUM_KEY_HASHES};
+use sealpir::{ClientHandle, Error};
+
+#[test]
+fn keyword_pir_test() {
+    let mut rng = rand::thread_rng();
+
+    let mut entries: Vec<(String, [u8; 64])> = Vec::new();
+    for i in 0..500 {
+        let mut value = [0u8; 64];
+        rng.fill_bytes(&mut value);
+        entries.push((format!("user-{}@example.com", i), value));
+    }
+
+    let mut server = KeywordPirServer::new(&entries, 64, 2048, 12, 2).unwrap();
+
+    // the client only needs the public parameters, including the hash seed
+    let params: KeywordParameters =
+        bincode::deserialize(&bincode::serialize(server.params()).unwrap()).unwrap();
+    let client = KeywordPirClient::new(&params).unwrap();
+
+    server.set_galois_key(client.get_key()).unwrap();
+
+    for (key, value) in entries.iter().step_by(97) {
+        let queries = client.gen_queries(key.as_bytes()).unwrap();
+        assert_eq!(queries.len(), NUM_KEY_HASHES);
+
+        let replies = server.gen_replies(&queries, client.handle()).unwrap();
+        let result = client.decode_replies(key.as_bytes(), &replies).unwrap();
+        assert_eq!(result.as_deref(), Some(&value[..]));
+    }
+
+    let missing = b"nobody@example.com";
+    let replies = server
+        .gen_replies(&client.gen_queries(missing).unwrap(), client.handle())
+        .unwrap();
+    assert_eq!(client.decode_replies(missing, &replies).unwrap(), None);
+}
+
+#[test]
+fn keyword_pir_invalid_input_test() {
+    let entries = vec![(b"a".to_vec(), vec![0u8; 8]), (b"b".to_vec(), vec![0u8; 7])];
+    assert_eq!(
+        KeywordPirServer::new(&entries, 8, 2048, 12, 2).err(),
+        Some(Error::SizeMismatch {
+            expected: 8,
+            actual: 7
+        })
+    );
+
+    let entries = vec![(b"a".to_vec(), vec![0u8; 8]), (b"a".to_vec(), vec![1u8; 8])];
+    assert!(matches!(
+        KeywordPirServer::new(&entries, 8, 2048, 12, 2),
+        Err(Error::InsertionFailed(_))
+    ));
+
+    let entries = vec![(b"a".to_vec(), vec![0u8; 8])];
+    let server = KeywordPirServer::new(&entries, 8, 2048, 12, 2).unwrap();
+    assert_eq!(
+        server.gen_replies(&[], ClientHandle::for_key(&[])).err(),
+        Some(Error::SizeMismatch {
+            expected: NUM_KEY_HASHES,
+            actual: 0
+        })
+    );
+}
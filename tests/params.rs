@@ -0,0 +1,131 @@
+use rand::{Rng, RngCore};
+use sealpir::client::PirClient;
+use sealpir::params::{Objective, PirParameters, Rejection};
+use sealpir::server::PirServer;
+use sealpir::Error;
+
+#[test]
+fn params_builder_test() {
+    let params = PirParameters::builder()
+        .ele_num(100)
+        .ele_size(288)
+        .build()
+        .unwrap();
+
+    assert_eq!(params, PirParameters::new(100, 288, 2048, 12, 2).unwrap());
+
+    // 288 bytes need 192 coefficients of 12 bits, so 10 elements fit in 2048 coefficients
+    assert_eq!(params.elements_per_plaintext(), 10);
+    assert_eq!(params.num_plaintexts(), 10);
+    assert_eq!(params.plaintext_size(), 2048 * 12 / 8);
+    assert_eq!(params.nvec().len(), 2);
+    assert!(params.nvec().iter().product::<u64>() >= params.num_plaintexts());
+}
+
+#[test]
+fn params_validation_test() {
+    let invalid = |r: Result<PirParameters, Error>| matches!(r, Err(Error::InvalidParameters(_)));
+
+    assert!(invalid(PirParameters::builder().ele_size(288).build()));
+    assert!(invalid(PirParameters::new(0, 288, 2048, 12, 2)));
+    assert!(invalid(PirParameters::new(100, 0, 2048, 12, 2)));
+    assert!(invalid(PirParameters::new(100, 288, 1000, 12, 2)));
+    assert!(invalid(PirParameters::new(100, 288, 2048, 1, 2)));
+    assert!(invalid(PirParameters::new(100, 288, 2048, 60, 2)));
+    assert!(invalid(PirParameters::new(100, 288, 2048, 12, 0)));
+}
+
+#[test]
+fn params_columns_test() {
+    let params = PirParameters::new(100, 288, 2048, 12, 2).unwrap();
+    assert_eq!(params.num_columns(), 1);
+    assert_eq!(params.column_size(), 288);
+
+    // 3072 bytes fit in one plaintext at log t = 12, so 10000 bytes need four columns
+    let params = PirParameters::new(100, 10000, 2048, 12, 2).unwrap();
+    assert_eq!(params.num_columns(), 4);
+    assert_eq!(params.column_size(), 2500);
+    assert_eq!(params.elements_per_plaintext(), 1);
+    assert_eq!(params.num_plaintexts(), 100);
+}
+
+#[test]
+fn params_serde_test() {
+    let params = PirParameters::new(1 << 16, 288, 2048, 20, 2).unwrap();
+    let bytes = bincode::serialize(&params).unwrap();
+    let copy: PirParameters = bincode::deserialize(&bytes).unwrap();
+
+    assert_eq!(params, copy);
+    assert_eq!(params.nvec(), copy.nvec());
+
+    // both sides can be built from their own copy of the parameters
+    let _server = PirServer::new(&params).unwrap();
+    let _client = PirClient::new(&copy).unwrap();
+
+    let mut bytes = bytes;
+    let d_offset = bytes.len() - 4; // d is the last u32
+    bytes[d_offset] = 0;
+    assert!(bincode::deserialize::<PirParameters>(&bytes).is_err());
+}
+
+#[test]
+fn params_recommend_test() {
+    for &objective in &[
+        Objective::QuerySize,
+        Objective::ReplySize,
+        Objective::ServerCompute,
+    ] {
+        let rec = PirParameters::recommend(1 << 16, 288, objective).unwrap();
+        assert!(rec.chosen.rejection.is_none());
+        assert_eq!(rec.params.log_plain_mod(), rec.chosen.log_plain_mod);
+        assert_eq!(rec.params.d(), rec.chosen.d);
+
+        let score = rec.chosen.score.unwrap();
+        for candidate in &rec.rejected {
+            match candidate.rejection {
+                Some(Rejection::Outscored) => assert!(candidate.score.unwrap() >= score),
+                Some(_) => assert!(candidate.score.is_none()),
+                None => panic!("rejected candidate without a reason"),
+            }
+        }
+    }
+
+    // a single dimension needs the fewest reply ciphertexts
+    let rec = PirParameters::recommend(1 << 16, 288, Objective::ReplySize).unwrap();
+    assert_eq!(rec.params.d(), 1);
+
+    assert!(PirParameters::recommend(100, 1 << 16, Objective::QuerySize).is_err());
+}
+
+#[test]
+fn params_recommend_retrieval_test() {
+    let num = 1000;
+    let objective = Objective::Weighted {
+        query_size: 1.0,
+        reply_size: 1.0,
+        server_compute: 1.0,
+    };
+    let params = PirParameters::recommend(num, 288, objective)
+        .unwrap()
+        .params;
+
+    let mut collection: Vec<[u8; 288]> = Vec::new();
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..num {
+        let mut x: [u8; 288] = [0; 288];
+        rng.fill_bytes(&mut x);
+        collection.push(x);
+    }
+
+    let mut server = PirServer::new(&params).unwrap();
+    let client = PirClient::new(&params).unwrap();
+    server.set_galois_key(client.get_key()).unwrap();
+    server.setup(&collection[..]).unwrap();
+
+    let index = rng.gen::<u32>() % num;
+    let query = client.gen_query(index).unwrap();
+    let reply = server.gen_reply(&query, client.handle()).unwrap();
+    let result = client.decode_reply_to_vec(index, &reply).unwrap();
+    assert_eq!(&result[..], &collection[index as usize][..]);
+}
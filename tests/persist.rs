@@ -0,0 +1,232 @@
+use rand::{Rng, RngCore};
+use sealpir::client::PirClient;
+use sealpir::params::PirParameters;
+use sealpir::server::PirServer;
+use sealpir::Error;
+use std::fs;
+use std::path::PathBuf;
+
+fn temp_path(name: &str) -> PathBuf {
+    let mut path = std::env::temp_dir();
+    path.push(format!(
+        "sealpir-{}-{}-{}",
+        name,
+        std::process::id(),
+        rand::thread_rng().gen::<u64>()
+    ));
+    path
+}
+
+#[test]
+fn persist_preprocessed_test() {
+    let num = 1000;
+    let ele_size = 5000; // two columns
+
+    let mut collection = vec![0u8; num as usize * ele_size];
+    let mut rng = rand::thread_rng();
+    rng.fill_bytes(&mut collection);
+
+    let params = PirParameters::new(num, ele_size as u32, 2048, 12, 2).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    server.setup_bytes(&collection, ele_size).unwrap();
+
+    let path = temp_path("persist");
+    server.save_preprocessed(&path).unwrap();
+    drop(server);
+
+    let mut server = PirServer::load_preprocessed(&path, &params).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let client = PirClient::new(&params).unwrap();
+    server.set_galois_key(client.get_key()).unwrap();
+
+    let index = rng.gen::<u32>() % num;
+    let query = client.gen_query(index).unwrap();
+    let reply = server.gen_reply(&query, client.handle()).unwrap();
+    let result = client.decode_reply_to_vec(index, &reply).unwrap();
+    let start = index as usize * ele_size;
+    assert_eq!(&result[..], &collection[start..start + ele_size]);
+}
+
+#[test]
+fn persist_invalid_file_test() {
+    let num = 100;
+    let params = PirParameters::new(num, 288, 2048, 12, 2).unwrap();
+    let server = PirServer::new(&params).unwrap();
+
+    let path = temp_path("invalid");
+    assert_eq!(
+        server.save_preprocessed(&path).err(),
+        Some(Error::DatabaseNotSet)
+    );
+
+    let mut server = server;
+    server
+        .setup_bytes(&vec![7u8; num as usize * 288], 288)
+        .unwrap();
+    server.save_preprocessed(&path).unwrap();
+
+    let other = PirParameters::new(num, 288, 2048, 16, 2).unwrap();
+    assert!(matches!(
+        PirServer::load_preprocessed(&path, &other),
+        Err(Error::InvalidParameters(_))
+    ));
+
+    let bytes = fs::read(&path).unwrap();
+
+    let mut corrupted = bytes.clone();
+    corrupted[100] ^= 1;
+    fs::write(&path, &corrupted).unwrap();
+    assert_eq!(
+        PirServer::load_preprocessed(&path, &params).err(),
+        Some(Error::InvalidFile("checksum mismatch".to_string()))
+    );
+
+    // a database short of the padded plaintext count is rejected before any reply
+    let shape = Some(Error::InvalidFile(
+        "database shape does not match the parameters".to_string(),
+    ));
+    let mut short = bytes.clone();
+    let mut count = [0u8; 8];
+    count.copy_from_slice(&short[36..44]);
+    let count = u64::from_le_bytes(count) - 1;
+    short[36..44].copy_from_slice(&count.to_le_bytes());
+    fs::write(&path, &short).unwrap();
+    assert_eq!(PirServer::load_preprocessed(&path, &params).err(), shape);
+    assert_eq!(PirServer::open_mapped(&path, &params).err(), shape);
+
+    fs::write(&path, &bytes[..bytes.len() - 100]).unwrap();
+    assert_eq!(
+        PirServer::load_preprocessed(&path, &params).err(),
+        Some(Error::InvalidFile("file is truncated".to_string()))
+    );
+
+    fs::write(&path, b"not a database").unwrap();
+    assert!(matches!(
+        PirServer::load_preprocessed(&path, &params),
+        Err(Error::InvalidFile(_))
+    ));
+
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(
+        PirServer::load_preprocessed(&path, &params),
+        Err(Error::Io(_))
+    ));
+}
+
+#[test]
+fn persist_mapped_test() {
+    let num = 1000;
+    let ele_size = 5000; // two columns
+
+    let mut collection = vec![0u8; num as usize * ele_size];
+    let mut rng = rand::thread_rng();
+    rng.fill_bytes(&mut collection);
+
+    let params = PirParameters::new(num, ele_size as u32, 2048, 12, 2).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    server.setup_bytes(&collection, ele_size).unwrap();
+
+    let path = temp_path("mapped");
+    server.save_preprocessed(&path).unwrap();
+    drop(server);
+
+    // several servers can map the same file
+    let mut first = PirServer::open_mapped(&path, &params).unwrap();
+    let mut second = PirServer::open_mapped(&path, &params).unwrap();
+
+    let client = PirClient::new(&params).unwrap();
+    first.set_galois_key(client.get_key()).unwrap();
+    second.set_galois_key(client.get_key()).unwrap();
+
+    for server in [&first, &second].iter() {
+        let index = rng.gen::<u32>() % num;
+        let query = client.gen_query(index).unwrap();
+        let reply = server.gen_reply(&query, client.handle()).unwrap();
+        let result = client.decode_reply_to_vec(index, &reply).unwrap();
+        let start = index as usize * ele_size;
+        assert_eq!(&result[..], &collection[start..start + ele_size]);
+    }
+
+    // saving a mapped database copies the file
+    let copy = temp_path("mapped-copy");
+    first.save_preprocessed(&copy).unwrap();
+    assert_eq!(fs::read(&copy).unwrap(), fs::read(&path).unwrap());
+    fs::remove_file(&copy).unwrap();
+
+    assert_eq!(
+        first.update_bytes(&collection, ele_size, 0).err(),
+        Some(Error::ReadOnlyDatabase)
+    );
+
+    // setting up replaces the mapped database
+    second.setup_bytes(&collection, ele_size).unwrap();
+    second.update_bytes(&collection, ele_size, 0).unwrap();
+
+    // the file must not change while it is mapped
+    drop(first);
+    drop(second);
+
+    let bytes = fs::read(&path).unwrap();
+    fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+    assert!(matches!(
+        PirServer::open_mapped(&path, &params),
+        Err(Error::InvalidFile(_))
+    ));
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn persist_free_slots_test() {
+    let ele_size = 288;
+    let mut rng = rand::thread_rng();
+    let mut random_element = || {
+        let mut element = vec![0u8; ele_size];
+        rng.fill_bytes(&mut element);
+        element
+    };
+
+    let params = PirParameters::new(20, ele_size as u32, 2048, 12, 2).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    server.setup_empty().unwrap();
+    let elements: Vec<Vec<u8>> = (0..10).map(|_| random_element()).collect();
+    for element in elements.iter() {
+        server.append(element).unwrap();
+    }
+    server.resize(40).unwrap();
+    server.delete(3).unwrap();
+    let saved_params = server.params().clone();
+
+    let path = temp_path("free-slots");
+    server.save_preprocessed(&path).unwrap();
+    drop(server);
+
+    // the epoch is restored from the file
+    let params = PirParameters::new(40, ele_size as u32, 2048, 12, 2).unwrap();
+    let mapped = PirServer::open_mapped(&path, &params).unwrap();
+    let mut server = PirServer::load_preprocessed(&path, &params).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    for server in [&mapped, &server].iter() {
+        assert_eq!(server.params(), &saved_params);
+        assert_eq!(server.params().epoch(), 1);
+        assert_eq!(server.len(), 9);
+        assert!(!server.is_full());
+    }
+
+    // the deleted slot is reused first, then the slots past the last one used
+    let element = random_element();
+    assert_eq!(server.append(&element).unwrap(), 3);
+    assert_eq!(server.append(&random_element()).unwrap(), 10);
+    assert_eq!(server.len(), 11);
+
+    let client = PirClient::new(server.params()).unwrap();
+    server.set_galois_key(client.get_key()).unwrap();
+    for &(index, expected) in [(3, &element), (7, &elements[7])].iter() {
+        let query = client.gen_query(index).unwrap();
+        let reply = server.gen_reply(&query, client.handle()).unwrap();
+        let result = client.decode_reply_to_vec(index, &reply).unwrap();
+        assert_eq!(&result, expected);
+    }
+}
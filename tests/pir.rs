@@ -1,6 +1,10 @@
 use rand::{Rng, RngCore};
 use sealpir::client::PirClient;
+use sealpir::params::{CostModel, PirParameters, CIPHERTEXT_SIZE};
 use sealpir::server::PirServer;
+use sealpir::{Error, PirQuery};
+use std::sync::Arc;
+use std::thread;
 
 #[test]
 fn pir_very_small_collection_test() {
@@ -20,21 +24,22 @@ fn pir_very_small_collection_test() {
 
     let truth = collection.clone();
 
-    let mut server = PirServer::new(num, 288, poly_degree, log_plain_mod, d);
-    let client = PirClient::new(num, 288, poly_degree, log_plain_mod, d);
+    let params = PirParameters::new(num, 288, poly_degree, log_plain_mod, d).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    let client = PirClient::new(&params).unwrap();
 
     {
         let key = client.get_key();
         println!("Key size {}", key.len());
-        server.set_galois_key(key, 0);
+        server.set_galois_key(key).unwrap();
     }
 
-    server.setup(&collection[..]);
+    server.setup(&collection[..]).unwrap();
 
     let index = rng.gen::<u32>() % num;
-    let query = client.gen_query(index);
-    let reply = server.gen_reply(&query, 0);
-    let result = client.decode_reply::<[u8; 288]>(index, &reply);
+    let query = client.gen_query(index).unwrap();
+    let reply = server.gen_reply(&query, client.handle()).unwrap();
+    let result = client.decode_reply::<[u8; 288]>(index, &reply).unwrap();
     assert_eq!(&result[..], &truth[index as usize][..]);
 }
 
@@ -56,20 +61,21 @@ fn pir_small_collection_test() {
 
     let truth = collection.clone();
 
-    let mut server = PirServer::new(num, 288, poly_degree, log_plain_mod, d);
-    let client = PirClient::new(num, 288, poly_degree, log_plain_mod, d);
+    let params = PirParameters::new(num, 288, poly_degree, log_plain_mod, d).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    let client = PirClient::new(&params).unwrap();
 
     {
         let key = client.get_key();
-        server.set_galois_key(key, 0);
+        server.set_galois_key(key).unwrap();
     }
 
-    server.setup(&collection[..]);
+    server.setup(&collection[..]).unwrap();
 
     let index = rng.gen::<u32>() % num;
-    let query = client.gen_query(index);
-    let reply = server.gen_reply(&query, 0);
-    let result = client.decode_reply_to_vec(index, &reply);
+    let query = client.gen_query(index).unwrap();
+    let reply = server.gen_reply(&query, client.handle()).unwrap();
+    let result = client.decode_reply_to_vec(index, &reply).unwrap();
     assert_eq!(&result[..], &truth[index as usize][..]);
 }
 
@@ -91,20 +97,21 @@ fn pir_small_collection_decode_to_vec_test() {
 
     let truth = collection.clone();
 
-    let mut server = PirServer::new(num, 288, poly_degree, log_plain_mod, d);
-    let client = PirClient::new(num, 288, poly_degree, log_plain_mod, d);
+    let params = PirParameters::new(num, 288, poly_degree, log_plain_mod, d).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    let client = PirClient::new(&params).unwrap();
 
     {
         let key = client.get_key();
-        server.set_galois_key(key, 0);
+        server.set_galois_key(key).unwrap();
     }
 
-    server.setup(&collection[..]);
+    server.setup(&collection[..]).unwrap();
 
     let index = rng.gen::<u32>() % num;
-    let query = client.gen_query(index);
-    let reply = server.gen_reply(&query, 0);
-    let result = client.decode_reply::<[u8; 288]>(index, &reply);
+    let query = client.gen_query(index).unwrap();
+    let reply = server.gen_reply(&query, client.handle()).unwrap();
+    let result = client.decode_reply::<[u8; 288]>(index, &reply).unwrap();
     assert_eq!(&result[..], &truth[index as usize][..]);
 }
 
@@ -124,28 +131,95 @@ fn pir_small_collection_update_test() {
         collection.push(x);
     }
 
-    let mut server = PirServer::new(num, 288, poly_degree, log_plain_mod, d);
-    let client = PirClient::new(num, 288, poly_degree, log_plain_mod, d);
+    let params = PirParameters::new(num, 288, poly_degree, log_plain_mod, d).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    let client = PirClient::new(&params).unwrap();
 
     {
         let key = client.get_key();
-        server.set_galois_key(key, 0);
+        server.set_galois_key(key).unwrap();
     }
 
-    server.setup(&collection[..]);
+    server.setup(&collection[..]).unwrap();
 
     let index = rng.gen::<u32>() % num;
     rng.fill_bytes(&mut collection[0]);
-    server.update(&collection[..], 0);
+    server.update(&collection[..], 0).unwrap();
 
     let truth = collection.clone();
 
-    let query = client.gen_query(index);
-    let reply = server.gen_reply(&query, 0);
-    let result = client.decode_reply::<[u8; 288]>(index, &reply);
+    let query = client.gen_query(index).unwrap();
+    let reply = server.gen_reply(&query, client.handle()).unwrap();
+    let result = client.decode_reply::<[u8; 288]>(index, &reply).unwrap();
     assert_eq!(&result[..], &truth[index as usize][..]);
 }
 
+#[test]
+fn pir_update_elements_test() {
+    let poly_degree = 2048;
+    let log_plain_mod = 12;
+    let d = 2;
+
+    // small elements share plaintexts, large ones are split across columns
+    for &(num, ele_size) in [(1000u32, 288usize), (50, 10000)].iter() {
+        let mut collection = vec![0u8; num as usize * ele_size];
+        let mut rng = rand::thread_rng();
+        rng.fill_bytes(&mut collection);
+
+        let params =
+            PirParameters::new(num, ele_size as u32, poly_degree, log_plain_mod, d).unwrap();
+        let mut server = PirServer::new(&params).unwrap();
+        let client = PirClient::new(&params).unwrap();
+        server.set_galois_key(client.get_key()).unwrap();
+        server.setup_bytes(&collection, ele_size).unwrap();
+
+        // neighbours in one plaintext, the last element and a repeated index
+        let indices = [0, 1, num as usize - 1, 1];
+        let mut updates = Vec::new();
+        for &index in indices.iter() {
+            let mut element = vec![0u8; ele_size];
+            rng.fill_bytes(&mut element);
+            collection[index * ele_size..][..ele_size].copy_from_slice(&element);
+            updates.push((index, element));
+        }
+        server.update_elements(&updates).unwrap();
+
+        for &index in [0, 1, 2, num - 1].iter() {
+            let query = client.gen_query(index).unwrap();
+            let reply = server.gen_reply(&query, client.handle()).unwrap();
+            let result = client.decode_reply_to_vec(index, &reply).unwrap();
+            let start = index as usize * ele_size;
+            assert_eq!(&result[..], &collection[start..start + ele_size]);
+        }
+
+        // nothing is applied if one update is invalid
+        let element = vec![0u8; ele_size];
+        assert_eq!(
+            server
+                .update_elements(&[(0, &element[..]), (num as usize, &element[..])])
+                .err(),
+            Some(Error::IndexOutOfRange {
+                index: num as usize,
+                len: num as usize
+            })
+        );
+        assert_eq!(
+            server
+                .update_elements(&[(0, &element[..]), (1, &element[1..])])
+                .err(),
+            Some(Error::SizeMismatch {
+                expected: ele_size,
+                actual: ele_size - 1
+            })
+        );
+
+        let query = client.gen_query(0).unwrap();
+        let reply = server.gen_reply(&query, client.handle()).unwrap();
+        let result = client.decode_reply_to_vec(0, &reply).unwrap();
+        assert_eq!(&result[..], &collection[..ele_size]);
+    }
+}
+
 #[test]
 fn pir_medium_collection_test() {
     let poly_degree = 2048;
@@ -164,20 +238,21 @@ fn pir_medium_collection_test() {
 
     let truth = collection.clone();
 
-    let mut server = PirServer::new(num, 288, poly_degree, log_plain_mod, d);
-    let client = PirClient::new(num, 288, poly_degree, log_plain_mod, d);
+    let params = PirParameters::new(num, 288, poly_degree, log_plain_mod, d).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    let client = PirClient::new(&params).unwrap();
 
     {
         let key = client.get_key();
-        server.set_galois_key(key, 0);
+        server.set_galois_key(key).unwrap();
     }
 
-    server.setup(&collection[..]);
+    server.setup(&collection[..]).unwrap();
 
     let index = rng.gen::<u32>() % num;
-    let query = client.gen_query(index);
-    let reply = server.gen_reply(&query, 0);
-    let result = client.decode_reply::<[u8; 288]>(index, &reply);
+    let query = client.gen_query(index).unwrap();
+    let reply = server.gen_reply(&query, client.handle()).unwrap();
+    let result = client.decode_reply::<[u8; 288]>(index, &reply).unwrap();
     assert_eq!(&result[..], &truth[index as usize][..]);
 }
 
@@ -199,17 +274,18 @@ fn pir_large_collection_test() {
 
     let truth = collection.clone();
 
-    let mut server = PirServer::new(num, 288, poly_degree, log_plain_mod, d);
-    let client = PirClient::new(num, 288, poly_degree, log_plain_mod, d);
+    let params = PirParameters::new(num, 288, poly_degree, log_plain_mod, d).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    let client = PirClient::new(&params).unwrap();
     let key = client.get_key();
 
-    server.set_galois_key(key, 0);
-    server.setup(&collection[..]);
+    server.set_galois_key(key).unwrap();
+    server.setup(&collection[..]).unwrap();
 
     let index = rng.gen::<u32>() % num;
-    let query = client.gen_query(index);
-    let reply = server.gen_reply(&query, 0);
-    let result = client.decode_reply::<[u8; 288]>(index, &reply);
+    let query = client.gen_query(index).unwrap();
+    let reply = server.gen_reply(&query, client.handle()).unwrap();
+    let result = client.decode_reply::<[u8; 288]>(index, &reply).unwrap();
     assert_eq!(&result[..], &truth[index as usize][..]);
 }
 
@@ -231,21 +307,22 @@ fn pir_very_large_collection_test() {
 
     let truth = collection.clone();
 
-    let mut server = PirServer::new(num, 288, poly_degree, log_plain_mod, d);
-    let client = PirClient::new(num, 288, poly_degree, log_plain_mod, d);
+    let params = PirParameters::new(num, 288, poly_degree, log_plain_mod, d).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    let client = PirClient::new(&params).unwrap();
 
     {
         let key = client.get_key();
         println!("Key size {}", key.len());
-        server.set_galois_key(key, 0);
+        server.set_galois_key(key).unwrap();
     }
 
-    server.setup(&collection[..]);
+    server.setup(&collection[..]).unwrap();
 
     let index = rng.gen::<u32>() % num;
-    let query = client.gen_query(index);
-    let reply = server.gen_reply(&query, 0);
-    let result = client.decode_reply::<[u8; 288]>(index, &reply);
+    let query = client.gen_query(index).unwrap();
+    let reply = server.gen_reply(&query, client.handle()).unwrap();
+    let result = client.decode_reply::<[u8; 288]>(index, &reply).unwrap();
     assert_eq!(&result[..], &truth[index as usize][..]);
 }
 
@@ -267,24 +344,212 @@ fn pir_largest_collection_test() {
 
     let truth = collection.clone();
 
-    let mut server = PirServer::new(num, 288, poly_degree, log_plain_mod, d);
-    let client = PirClient::new(num, 288, poly_degree, log_plain_mod, d);
+    let params = PirParameters::new(num, 288, poly_degree, log_plain_mod, d).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    let client = PirClient::new(&params).unwrap();
 
     {
         let key = client.get_key();
         println!("Key size {}", key.len());
-        server.set_galois_key(key, 0);
+        server.set_galois_key(key).unwrap();
     }
 
-    server.setup(&collection[..]);
+    server.setup(&collection[..]).unwrap();
 
     let index = rng.gen::<u32>() % num;
-    let query = client.gen_query(index);
-    let reply = server.gen_reply(&query, 0);
-    let result = client.decode_reply::<[u8; 288]>(index, &reply);
+    let query = client.gen_query(index).unwrap();
+    let reply = server.gen_reply(&query, client.handle()).unwrap();
+    let result = client.decode_reply::<[u8; 288]>(index, &reply).unwrap();
     assert_eq!(&result[..], &truth[index as usize][..]);
 }
 
+#[test]
+fn pir_large_element_test() {
+    let poly_degree = 2048;
+    let log_plain_mod = 12;
+    let num = 50;
+    let ele_size = 10000; // more than the 3072 bytes one plaintext holds
+    let d = 2;
+
+    let mut collection = vec![0u8; num as usize * ele_size];
+    let mut rng = rand::thread_rng();
+    rng.fill_bytes(&mut collection);
+
+    let params = PirParameters::new(num, ele_size as u32, poly_degree, log_plain_mod, d).unwrap();
+    assert_eq!(params.num_columns(), 4);
+
+    let mut server = PirServer::new(&params).unwrap();
+    let client = PirClient::new(&params).unwrap();
+
+    server.set_galois_key(client.get_key()).unwrap();
+    server.setup_bytes(&collection, ele_size).unwrap();
+
+    let index = rng.gen::<u32>() % num;
+    let query = client.gen_query(index).unwrap();
+    let reply = server.gen_reply(&query, client.handle()).unwrap();
+    assert_eq!(
+        reply.num as u64,
+        CostModel::from_params(&params).reply_ciphertexts
+    );
+
+    let result = client.decode_reply_to_vec(index, &reply).unwrap();
+    let start = index as usize * ele_size;
+    assert_eq!(&result[..], &collection[start..start + ele_size]);
+}
+
+#[test]
+fn pir_streaming_setup_test() {
+    let poly_degree = 2048;
+    let log_plain_mod = 12;
+    let d = 2;
+
+    // small elements share plaintexts, large ones are split across columns
+    for &(num, ele_size) in [(1000u32, 288usize), (50, 10000)].iter() {
+        let mut collection = vec![0u8; num as usize * ele_size];
+        let mut rng = rand::thread_rng();
+        rng.fill_bytes(&mut collection);
+
+        let params =
+            PirParameters::new(num, ele_size as u32, poly_degree, log_plain_mod, d).unwrap();
+        let client = PirClient::new(&params).unwrap();
+
+        let mut from_iter = PirServer::new(&params).unwrap();
+        from_iter.set_galois_key(client.get_key()).unwrap();
+        from_iter
+            .setup_from_iter(collection.chunks(ele_size))
+            .unwrap();
+
+        let mut from_reader = PirServer::new(&params).unwrap();
+        from_reader.set_galois_key(client.get_key()).unwrap();
+        from_reader.setup_from_reader(&collection[..]).unwrap();
+
+        for &index in [0, rng.gen::<u32>() % num, num - 1].iter() {
+            let query = client.gen_query(index).unwrap();
+            let start = index as usize * ele_size;
+            for server in [&from_iter, &from_reader].iter() {
+                let reply = server.gen_reply(&query, client.handle()).unwrap();
+                let result = client.decode_reply_to_vec(index, &reply).unwrap();
+                assert_eq!(&result[..], &collection[start..start + ele_size]);
+            }
+        }
+    }
+}
+
+#[test]
+fn pir_streaming_setup_invalid_test() {
+    let num = 100;
+    let ele_size = 288;
+    let params = PirParameters::new(num, ele_size as u32, 2048, 12, 2).unwrap();
+    let client = PirClient::new(&params).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    server.set_galois_key(client.get_key()).unwrap();
+
+    let collection = vec![0u8; (num as usize + 1) * ele_size];
+
+    assert_eq!(
+        server
+            .setup_from_iter(collection[ele_size..].chunks(ele_size).skip(1))
+            .err(),
+        Some(Error::SizeMismatch {
+            expected: num as usize,
+            actual: num as usize - 1
+        })
+    );
+    assert_eq!(
+        server.setup_from_iter(collection.chunks(ele_size)).err(),
+        Some(Error::SizeMismatch {
+            expected: num as usize,
+            actual: num as usize + 1
+        })
+    );
+    assert_eq!(
+        server
+            .setup_from_iter(collection.chunks(ele_size - 1))
+            .err(),
+        Some(Error::SizeMismatch {
+            expected: ele_size,
+            actual: ele_size - 1
+        })
+    );
+    assert_eq!(
+        server.setup_from_reader(&collection[2 * ele_size..]).err(),
+        Some(Error::SizeMismatch {
+            expected: num as usize * ele_size,
+            actual: (num as usize - 1) * ele_size
+        })
+    );
+
+    // a failed setup leaves no database to answer from
+    let query = client.gen_query(0).unwrap();
+    assert_eq!(
+        server.gen_reply(&query, client.handle()).err(),
+        Some(Error::DatabaseNotSet)
+    );
+}
+
+#[test]
+fn pir_concurrent_reply_test() {
+    let poly_degree = 2048;
+    let log_plain_mod = 12;
+    let num = 1000;
+    let d = 2;
+    let threads = 8;
+    let queries_per_thread = 4;
+
+    let mut collection: Vec<[u8; 288]> = Vec::new();
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..num {
+        let mut x: [u8; 288] = [0; 288];
+        rng.fill_bytes(&mut x);
+        collection.push(x);
+    }
+
+    let params = PirParameters::new(num, 288, poly_degree, log_plain_mod, d).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    server.setup(&collection[..]).unwrap();
+
+    // one client per thread, each with its own key
+    let clients: Vec<PirClient> = (0..threads)
+        .map(|_| PirClient::new(&params).unwrap())
+        .collect();
+    for client in clients.iter() {
+        assert_eq!(
+            server.set_galois_key(client.get_key()).unwrap(),
+            client.handle()
+        );
+    }
+
+    let server = Arc::new(server);
+    let handles: Vec<_> = clients
+        .iter()
+        .map(|client| {
+            let queries: Vec<(u32, PirQuery)> = (0..queries_per_thread)
+                .map(|_| {
+                    let index = rng.gen::<u32>() % num;
+                    (index, client.gen_query(index).unwrap())
+                })
+                .collect();
+
+            let server = Arc::clone(&server);
+            let client_handle = client.handle();
+            thread::spawn(move || {
+                queries
+                    .into_iter()
+                    .map(|(index, query)| (index, server.gen_reply(&query, client_handle).unwrap()))
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+
+    for (client, handle) in clients.iter().zip(handles) {
+        for (index, reply) in handle.join().unwrap() {
+            let result = client.decode_reply::<[u8; 288]>(index, &reply).unwrap();
+            assert_eq!(&result[..], &collection[index as usize][..]);
+        }
+    }
+}
+
 #[test]
 fn pir_sizes() {
     let size = 288;
@@ -312,16 +577,16 @@ fn pir_sizes() {
 
         for d in &ds {
             for logt in &logts {
-                let mut server = PirServer::new(num, size, 2048, *logt, *d);
-
-                let client = PirClient::new(num, size, 2048, *logt, *d);
+                let params = PirParameters::new(num, size, 2048, *logt, *d).unwrap();
+                let mut server = PirServer::new(&params).unwrap();
+                let client = PirClient::new(&params).unwrap();
                 let galois = client.get_key();
 
-                server.setup(&collection[..]);
-                server.set_galois_key(&galois, 0);
+                server.setup(&collection[..]).unwrap();
+                server.set_galois_key(galois).unwrap();
 
-                let query = client.gen_query(index);
-                let reply = server.gen_reply(&query, 0);
+                let query = client.gen_query(index).unwrap();
+                let reply = server.gen_reply(&query, client.handle()).unwrap();
 
                 println!(
                     "query: num {}, logt {}, d {}, size {}",
@@ -341,3 +606,357 @@ fn pir_sizes() {
         }
     }
 }
+
+#[test]
+fn pir_cost_model_test() {
+    let num = 1000;
+    let size = 288;
+    let index = 70;
+
+    let mut collection: Vec<[u8; 288]> = Vec::new();
+    let mut rng = rand::thread_rng();
+    for _ in 0..num {
+        let mut x: [u8; 288] = [0; 288];
+        rng.fill_bytes(&mut x);
+        collection.push(x);
+    }
+
+    for &(logt, d) in [(12, 1), (12, 2), (20, 2)].iter() {
+        let cost = CostModel::new(num, size, 2048, logt, d).unwrap();
+
+        let params = PirParameters::new(num, size, 2048, logt, d).unwrap();
+        let mut server = PirServer::new(&params).unwrap();
+        let client = PirClient::new(&params).unwrap();
+        let galois = client.get_key();
+
+        server.setup(&collection[..]).unwrap();
+        server.set_galois_key(galois).unwrap();
+
+        let query = client.gen_query(index).unwrap();
+        let reply = server.gen_reply(&query, client.handle()).unwrap();
+
+        assert_eq!(cost.query_bytes, query.query.len() as u64);
+        assert_eq!(cost.reply_bytes, reply.reply.len() as u64);
+        assert_eq!(cost.reply_ciphertexts, reply.num as u64);
+
+        // serialization adds a small header to the keys
+        let key_size = galois.len() as u64;
+        assert!(cost.galois_key_bytes <= key_size);
+        assert!(key_size - cost.galois_key_bytes < cost.galois_key_bytes / 20);
+    }
+}
+
+#[test]
+fn pir_invalid_input_test() {
+    let poly_degree = 2048;
+    let log_plain_mod = 12;
+    let num = 100;
+    let d = 2;
+
+    let mut collection: Vec<[u8; 288]> = Vec::new();
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..num {
+        let mut x: [u8; 288] = [0; 288];
+        rng.fill_bytes(&mut x);
+        collection.push(x);
+    }
+
+    let params = PirParameters::new(num, 288, poly_degree, log_plain_mod, d).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    let client = PirClient::new(&params).unwrap();
+
+    assert_eq!(
+        client.gen_query(num).err(),
+        Some(Error::IndexOutOfRange {
+            index: num as usize,
+            len: num as usize
+        })
+    );
+
+    let query = client.gen_query(0).unwrap();
+    assert_eq!(
+        server.gen_reply(&query, client.handle()).err(),
+        Some(Error::DatabaseNotSet)
+    );
+
+    assert_eq!(
+        server.setup(&collection[1..]).err(),
+        Some(Error::SizeMismatch {
+            expected: num as usize,
+            actual: num as usize - 1
+        })
+    );
+    assert!(server.setup_bytes(&[0u8; 100], 100).is_err());
+    server.setup(&collection[..]).unwrap();
+
+    assert_eq!(
+        server.gen_reply(&query, client.handle()).err(),
+        Some(Error::UnknownClient(client.handle()))
+    );
+    let handle = server.set_galois_key(client.get_key()).unwrap();
+
+    let truncated = PirQuery {
+        query: query.query[..query.query.len() - 1].to_vec(),
+        num: query.num,
+    };
+    assert!(matches!(
+        server.gen_reply(&truncated, handle),
+        Err(Error::MalformedCiphertext(_))
+    ));
+
+    let reply = server.gen_reply(&query, handle).unwrap();
+    assert!(client.decode_reply::<[u8; 100]>(0, &reply).is_err());
+    assert_eq!(
+        client.decode_reply_to_vec(0, &reply).unwrap(),
+        &collection[0][..]
+    );
+}
+
+#[test]
+fn pir_corrupted_input_test() {
+    let poly_degree = 2048;
+    let log_plain_mod = 12;
+    let num = 100;
+    let d = 2;
+
+    let mut collection: Vec<[u8; 288]> = Vec::new();
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..num {
+        let mut x: [u8; 288] = [0; 288];
+        rng.fill_bytes(&mut x);
+        collection.push(x);
+    }
+
+    let params = PirParameters::new(num, 288, poly_degree, log_plain_mod, d).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    let client = PirClient::new(&params).unwrap();
+
+    let mut key = client.get_key().clone();
+    for b in key.iter_mut().take(64) {
+        *b = 0xff;
+    }
+    assert!(server.set_galois_key(&key).is_err());
+    server.set_galois_key(client.get_key()).unwrap();
+    server.setup(&collection[..]).unwrap();
+
+    let mut query = client.gen_query(0).unwrap();
+    for b in query.query.iter_mut().take(64) {
+        *b = 0xff;
+    }
+    assert!(server.gen_reply(&query, client.handle()).is_err());
+
+    let query = client.gen_query(0).unwrap();
+    let mut reply = server.gen_reply(&query, client.handle()).unwrap();
+    for b in reply.reply.iter_mut().take(64) {
+        *b = 0xff;
+    }
+    assert!(client.decode_reply_to_vec(0, &reply).is_err());
+}
+
+#[test]
+fn pir_compressed_test() {
+    let num = 100;
+    let ele_size = 5000; // two columns
+
+    let mut collection = vec![0u8; num as usize * ele_size];
+    rand::thread_rng().fill_bytes(&mut collection);
+
+    let params = PirParameters::new(num, ele_size as u32, 2048, 12, 2).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    server.setup_bytes(&collection, ele_size).unwrap();
+    let client = PirClient::new(&params).unwrap();
+
+    // both forms of the key identify the same client
+    let key = client.get_compressed_key();
+    assert!(key.len() < client.get_key().len() * 6 / 10);
+    assert_eq!(server.set_galois_key(key).unwrap(), client.handle());
+    assert_eq!(
+        server.set_galois_key(client.get_key()).unwrap(),
+        client.handle()
+    );
+    assert_eq!(server.client_count(), 1);
+
+    let index = 42;
+    let query = client.gen_compressed_query(index).unwrap();
+    assert!(query.query.len() < client.gen_query(index).unwrap().query.len() * 6 / 10);
+    let reply = server.gen_reply(&query, client.handle()).unwrap();
+    let result = client.decode_reply_to_vec(index, &reply).unwrap();
+    let start = index as usize * ele_size;
+    assert_eq!(&result[..], &collection[start..start + ele_size]);
+
+    // a seeded key is not a query
+    let query = PirQuery {
+        query: key.to_vec(),
+        num: query.num,
+    };
+    assert!(matches!(
+        server.gen_reply(&query, client.handle()),
+        Err(Error::MalformedCiphertext(_))
+    ));
+
+    let mut query = client.gen_compressed_query(index).unwrap();
+    query.query.truncate(query.query.len() - 1);
+    assert!(matches!(
+        server.gen_reply(&query, client.handle()),
+        Err(Error::MalformedCiphertext(_))
+    ));
+}
+
+#[test]
+fn pir_typed_elements_test() {
+    let num = 100;
+
+    // elements with an alignment larger than one byte
+    let mut rng = rand::thread_rng();
+    let collection: Vec<[u64; 36]> = (0..num)
+        .map(|_| {
+            let mut x = [0u64; 36];
+            rng.fill(&mut x[..]);
+            x
+        })
+        .collect();
+
+    let params = PirParameters::new(num, 288, 2048, 12, 2).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    let client = PirClient::new(&params).unwrap();
+    server.set_galois_key(client.get_key()).unwrap();
+    server.setup(&collection[..]).unwrap();
+
+    let index = rng.gen::<u32>() % num;
+    let query = client.gen_query(index).unwrap();
+    let reply = server.gen_reply(&query, client.handle()).unwrap();
+    let result = client.decode_reply::<[u64; 36]>(index, &reply).unwrap();
+    assert_eq!(result, collection[index as usize]);
+
+    assert_eq!(
+        client.decode_reply::<[u64; 35]>(index, &reply).err(),
+        Some(Error::SizeMismatch {
+            expected: 288,
+            actual: 280
+        })
+    );
+    assert_eq!(
+        server.setup::<()>(&[(); 100]).err(),
+        Some(Error::SizeMismatch {
+            expected: 288,
+            actual: 0
+        })
+    );
+}
+
+#[test]
+fn pir_decode_reply_all_test() {
+    let num = 95;
+    let ele_size = 288;
+
+    let mut collection = vec![0u8; num as usize * ele_size];
+    rand::thread_rng().fill_bytes(&mut collection);
+
+    let params = PirParameters::new(num, ele_size as u32, 2048, 12, 2).unwrap();
+    let per_plaintext = params.elements_per_plaintext();
+    assert!(per_plaintext > 1);
+
+    let mut server = PirServer::new(&params).unwrap();
+    server.setup_bytes(&collection, ele_size).unwrap();
+    let client = PirClient::new(&params).unwrap();
+    server.set_galois_key(client.get_key()).unwrap();
+
+    // the last plaintext is only partly filled
+    for &index in [42, num - 2].iter() {
+        let query = client.gen_query(index).unwrap();
+        let reply = server.gen_reply(&query, client.handle()).unwrap();
+        let elements = client.decode_reply_all(index, &reply).unwrap();
+
+        let first = index - index % per_plaintext;
+        let last = (first + per_plaintext).min(num);
+        let indices: Vec<u32> = elements.iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, (first..last).collect::<Vec<u32>>());
+        for (i, element) in elements {
+            let start = i as usize * ele_size;
+            assert_eq!(&element[..], &collection[start..start + ele_size]);
+        }
+    }
+}
+
+#[test]
+fn pir_decrypt_reply_test() {
+    let num = 100;
+    let ele_size = 5000; // two columns
+
+    let mut collection = vec![0u8; num as usize * ele_size];
+    rand::thread_rng().fill_bytes(&mut collection);
+
+    let params = PirParameters::new(num, ele_size as u32, 2048, 12, 2).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    server.setup_bytes(&collection, ele_size).unwrap();
+    let client = PirClient::new(&params).unwrap();
+    server.set_galois_key(client.get_key()).unwrap();
+
+    let index = 42;
+    let query = client.gen_query(index).unwrap();
+    let reply = server.gen_reply(&query, client.handle()).unwrap();
+    let decoded = client.decrypt_reply(index, &reply).unwrap();
+    assert!(decoded.indices().contains(&index));
+    assert_eq!(
+        decoded.bytes().len(),
+        params.num_columns() as usize * params.plaintext_size()
+    );
+
+    // every element of the plaintext comes from the one decryption
+    for i in decoded.indices() {
+        let start = i as usize * ele_size;
+        let element = decoded.element(i).unwrap();
+        assert_eq!(&element[..], &collection[start..start + ele_size]);
+
+        let chunks = decoded.element_chunks(i).unwrap();
+        assert_eq!(chunks.len(), params.num_columns() as usize);
+        assert_eq!(chunks.concat(), element);
+    }
+    assert!(decoded.element(decoded.indices().end).is_none());
+    assert_eq!(
+        client.decode_reply_to_vec(index, &reply).unwrap(),
+        decoded.element(index).unwrap()
+    );
+}
+
+#[test]
+fn pir_noise_budget_test() {
+    let num = 1000;
+    let ele_size = 288;
+
+    let mut collection = vec![0u8; num as usize * ele_size];
+    rand::thread_rng().fill_bytes(&mut collection);
+
+    let params = PirParameters::new(num, ele_size as u32, 2048, 12, 2).unwrap();
+    assert!(params.expected_noise_budget() > 0.0);
+
+    let mut server = PirServer::new(&params).unwrap();
+    server.setup_bytes(&collection, ele_size).unwrap();
+    let client = PirClient::new(&params).unwrap();
+    server.set_galois_key(client.get_key()).unwrap();
+
+    let index = 42;
+    let query = client.gen_query(index).unwrap();
+    let mut reply = server.gen_reply(&query, client.handle()).unwrap();
+    assert!(reply.num > 1);
+
+    let budgets = client.noise_budgets(&reply).unwrap();
+    assert_eq!(budgets.len(), reply.num as usize);
+    assert!(budgets.iter().all(|&budget| budget > 0));
+    let decoded = client.decrypt_reply(index, &reply).unwrap();
+    assert_eq!(decoded.noise_budgets(), &budgets[..]);
+
+    // zeroing the second polynomial of a ciphertext leaves nothing but noise
+    let poly_size = params.poly_degree() as usize * 8;
+    let end = 2 * CIPHERTEXT_SIZE;
+    for byte in &mut reply.reply[end - poly_size..end] {
+        *byte = 0;
+    }
+    assert_eq!(client.noise_budgets(&reply).unwrap()[1], 0);
+    assert!(matches!(
+        client.decode_reply_to_vec(index, &reply),
+        Err(Error::NoiseBudgetExhausted { .. })
+    ));
+}
@@ -0,0 +1,90 @@
+use rand::{Rng, RngCore};
+use sealpir::client::PirClient;
+use sealpir::params::PirParameters;
+use sealpir::record::SerdePirDatabase;
+use sealpir::server::PirServer;
+use sealpir::Error;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct User {
+    id: u64,
+    public_key: Vec<u8>,
+    name: String,
+    created: u64,
+}
+
+fn random_users(num: usize) -> Vec<User> {
+    let mut rng = rand::thread_rng();
+    (0..num)
+        .map(|i| {
+            let mut public_key = vec![0u8; 32];
+            rng.fill_bytes(&mut public_key);
+            User {
+                id: i as u64,
+                public_key,
+                name: format!("user {}", i),
+                created: rng.gen(),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn record_test() {
+    let num = 100;
+    let params = PirParameters::new(num, 128, 2048, 12, 2).unwrap();
+    let mut users = random_users(num as usize);
+    let db = SerdePirDatabase::new(&params, &users).unwrap();
+
+    let mut server = PirServer::new(&params).unwrap();
+    server.setup_records(&db).unwrap();
+    let client = PirClient::new(&params).unwrap();
+    server.set_galois_key(client.get_key()).unwrap();
+
+    let index = 42;
+    let query = client.gen_query(index).unwrap();
+    let reply = server.gen_reply(&query, client.handle()).unwrap();
+    let user: User = client.decode_record(index, &reply).unwrap();
+    assert_eq!(user, users[index as usize]);
+
+    users[index as usize].name = "renamed".to_string();
+    let update = SerdePirDatabase::encode(&params, index as usize, &users[index as usize]);
+    server.update_elements(&[update.unwrap()]).unwrap();
+    let reply = server.gen_reply(&query, client.handle()).unwrap();
+    let user: User = client.decode_record(index, &reply).unwrap();
+    assert_eq!(user, users[index as usize]);
+}
+
+#[test]
+fn record_invalid_test() {
+    let num = 10;
+    let params = PirParameters::new(num, 128, 2048, 12, 2).unwrap();
+    let mut users = random_users(num as usize);
+
+    users[3].public_key = vec![0u8; 128];
+    assert!(matches!(
+        SerdePirDatabase::new(&params, &users).err(),
+        Some(Error::RecordTooLarge {
+            index: 3,
+            slot_size: 128,
+            ..
+        })
+    ));
+    assert!(matches!(
+        SerdePirDatabase::new(&params, &users[..5]).err(),
+        Some(Error::SizeMismatch {
+            expected: 10,
+            actual: 5
+        })
+    ));
+
+    users[3].public_key.truncate(32);
+    let db = SerdePirDatabase::new(&params, &users).unwrap();
+    let other = PirParameters::new(num, 256, 2048, 12, 2).unwrap();
+    let mut server = PirServer::new(&other).unwrap();
+    assert!(matches!(
+        server.setup_records(&db),
+        Err(Error::InvalidParameters(_))
+    ));
+}
@@ -0,0 +1,94 @@
+use rand::{Rng, RngCore};
+use sealpir::varlen::{VarLenDatabase, VarLenParameters, VarLenPirClient, VarLenPirServer};
+use sealpir::Error;
+
+fn random_records(num: usize, max_len: usize) -> Vec<Vec<u8>> {
+    let mut rng = rand::thread_rng();
+    (0..num)
+        .map(|_| {
+            let mut record = vec![0u8; rng.gen_range(0..=max_len)];
+            rng.fill_bytes(&mut record);
+            record
+        })
+        .collect()
+}
+
+#[test]
+fn varlen_pir_test() {
+    let records = random_records(200, 1000);
+    let db = VarLenDatabase::builder()
+        .records(records.clone())
+        .build()
+        .unwrap();
+    assert_eq!(db.params().num_classes(), 1);
+
+    let mut server = VarLenPirServer::new(&db).unwrap();
+    let client = VarLenPirClient::new(db.params()).unwrap();
+    server.set_galois_key(client.get_key()).unwrap();
+
+    let index = rand::thread_rng().gen::<u32>() % 200;
+    let queries = client.gen_queries(index).unwrap();
+    let replies = server.gen_replies(&queries, client.handle()).unwrap();
+    let result = client.decode_replies(index, &replies).unwrap();
+    assert_eq!(result, records[index as usize]);
+}
+
+#[test]
+fn varlen_pir_size_classes_test() {
+    let mut records = random_records(300, 200);
+    records.extend(random_records(20, 5000));
+    records.push(Vec::new());
+
+    let db = VarLenDatabase::builder()
+        .records(records.clone())
+        .size_classes(4)
+        .build()
+        .unwrap();
+
+    // the client only needs the public parameters
+    let params: VarLenParameters =
+        bincode::deserialize(&bincode::serialize(db.params()).unwrap()).unwrap();
+    assert_eq!(params.num_classes(), 4);
+    assert!(params.slot_sizes().windows(2).all(|w| w[0] < w[1]));
+
+    let mut server = VarLenPirServer::new(&db).unwrap();
+    let client = VarLenPirClient::new(&params).unwrap();
+    server.set_galois_key(client.get_key()).unwrap();
+
+    // one key serves every size class, also the one whose records span two columns
+    assert_eq!(server.key_memory_usage(), client.get_key().len());
+
+    for &index in [0, 150, 305, 320].iter() {
+        let queries = client.gen_queries(index).unwrap();
+        assert_eq!(queries.len(), 4);
+
+        let replies = server.gen_replies(&queries, client.handle()).unwrap();
+        let result = client.decode_replies(index, &replies).unwrap();
+        assert_eq!(result, records[index as usize]);
+    }
+}
+
+#[test]
+fn varlen_pir_invalid_input_test() {
+    assert!(matches!(
+        VarLenDatabase::builder().build(),
+        Err(Error::InvalidParameters(_))
+    ));
+    assert!(matches!(
+        VarLenDatabase::builder()
+            .push(vec![1, 2, 3])
+            .size_classes(0)
+            .build(),
+        Err(Error::InvalidParameters(_))
+    ));
+
+    let db = VarLenDatabase::builder()
+        .records(random_records(10, 100))
+        .build()
+        .unwrap();
+    let client = VarLenPirClient::new(db.params()).unwrap();
+    assert_eq!(
+        client.gen_queries(10).err(),
+        Some(Error::IndexOutOfRange { index: 10, len: 10 })
+    );
+}
@@ -0,0 +1,83 @@
+use rand::RngCore;
+use sealpir::client::PirClient;
+use sealpir::params::PirParameters;
+use sealpir::server::PirServer;
+use sealpir::{Error, PirQuery, PirReply};
+
+#[test]
+fn wire_round_trip_test() {
+    let num = 100;
+    let ele_size = 5000; // two columns
+
+    let mut collection = vec![0u8; num as usize * ele_size];
+    rand::thread_rng().fill_bytes(&mut collection);
+
+    let params = PirParameters::new(num, ele_size as u32, 2048, 12, 2).unwrap();
+    let mut server = PirServer::new(&params).unwrap();
+    server.setup_bytes(&collection, ele_size).unwrap();
+    let client = PirClient::new(&params).unwrap();
+    server.set_galois_key(client.get_key()).unwrap();
+
+    let index = 42;
+    let start = index as usize * ele_size;
+    for &compressed in [false, true].iter() {
+        let query = if compressed {
+            client.gen_compressed_query(index).unwrap()
+        } else {
+            client.gen_query(index).unwrap()
+        };
+        let bytes = query.to_bytes(client.params(), client.handle());
+
+        let (query, handle) = PirQuery::from_bytes(&bytes, server.params()).unwrap();
+        assert_eq!(handle, client.handle());
+        let reply = server.gen_reply(&query, handle).unwrap();
+        let bytes = reply.to_bytes(server.params(), handle);
+
+        let (reply, handle) = PirReply::from_bytes(&bytes, client.params()).unwrap();
+        assert_eq!(handle, client.handle());
+        let result = client.decode_reply_to_vec(index, &reply).unwrap();
+        assert_eq!(&result[..], &collection[start..start + ele_size]);
+    }
+}
+
+#[test]
+fn wire_invalid_test() {
+    let params = PirParameters::new(100, 288, 2048, 12, 2).unwrap();
+    let client = PirClient::new(&params).unwrap();
+    let query = client.gen_query(0).unwrap();
+    let bytes = query.to_bytes(&params, client.handle());
+
+    // parameters of another epoch are told apart
+    let resized = params.resized(100).unwrap();
+    assert_ne!(params.fingerprint(), resized.fingerprint());
+    assert!(matches!(
+        PirQuery::from_bytes(&bytes, &resized),
+        Err(Error::InvalidParameters(_))
+    ));
+
+    // a query is not a reply
+    assert!(matches!(
+        PirReply::from_bytes(&bytes, &params),
+        Err(Error::MalformedCiphertext(_))
+    ));
+
+    assert!(matches!(
+        PirQuery::from_bytes(&bytes[..bytes.len() - 1], &params),
+        Err(Error::MalformedCiphertext(_))
+    ));
+    assert!(matches!(
+        PirQuery::from_bytes(&bytes[..20], &params),
+        Err(Error::MalformedCiphertext(_))
+    ));
+
+    // the ciphertext count has to match the payload
+    let wrong = PirQuery {
+        query: query.query.clone(),
+        num: query.num + 1,
+    };
+    let bytes = wrong.to_bytes(&params, client.handle());
+    assert!(matches!(
+        PirQuery::from_bytes(&bytes, &params),
+        Err(Error::MalformedCiphertext(_))
+    ));
+}